@@ -0,0 +1,50 @@
+//! Dedicated Item Wars server. Runs headless, set up from a TOML config,
+//! until it gets SIGINT or SIGTERM.
+
+use std::path::Path;
+use std::sync::atomic::Ordering;
+
+use clap::App;
+use item_wars::server::config::{ServerConfig, DEFAULT_CONFIG_FILE};
+use item_wars::server::GameServer;
+use item_wars::shared::{load_item_catalog, load_maps};
+
+fn load_config(file: Option<&str>) -> ServerConfig {
+    let path = Path::new(file.unwrap_or(DEFAULT_CONFIG_FILE));
+    if file.is_none() && !path.exists() {
+        println!("No {} found, using the default config", DEFAULT_CONFIG_FILE);
+        return ServerConfig::default();
+    }
+    match ServerConfig::load(path) {
+        Ok(config) => config,
+        Err(e) => panic!("{}: {}", path.display(), e),
+    }
+}
+
+fn main() {
+    let matches = App::new("item_wars_server")
+        .version(env!("CARGO_PKG_VERSION"))
+        .arg("-c --config=[FILE] 'Server config to load (default server.toml)'")
+        .get_matches();
+
+    let config = load_config(matches.value_of("config"));
+    let catalog = load_item_catalog(config.items.as_deref());
+    let maps = load_maps(config.maps.as_deref(), &config.default_map);
+    println!("Loaded maps: {:?}", maps.keys().collect::<Vec<_>>());
+    println!(
+        "Starting Item Wars Server on {} at {} ticks per second, up to {} games of {} players",
+        config.bind, config.tick_rate, config.max_games, config.max_players,
+    );
+
+    let bind = config.bind.clone();
+    let mut server = GameServer::new(config, catalog, maps);
+    let shutdown = server.shutdown_handle();
+    ctrlc::set_handler(move || {
+        println!("Got a signal to stop");
+        shutdown.store(true, Ordering::SeqCst);
+    }).expect("Could not listen for SIGINT and SIGTERM");
+    if let Err(e) = server.host() {
+        println!("Could not host on {}: {}", bind, e);
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,70 @@
+//! The client's view onto an arena that may be bigger than the window.
+//!
+//! Everything in the world is positioned in world coordinates and moved
+//! onto the screen with `Camera::to_screen` when it's drawn. The camera
+//! only moves once the player it follows leaves a dead zone in the middle
+//! of the view, and never shows anything past the edges of the map.
+
+use crate::shared::Position;
+
+#[derive(Debug, Clone)]
+pub struct Camera {
+    /// World position of the top left corner of the window
+    pub x: f32,
+    pub y: f32,
+    /// Size of the window
+    view: (f32, f32),
+    /// Size of the box in the middle of the view the target can move around in
+    dead_zone: (f32, f32),
+}
+
+impl Camera {
+    pub fn new(view: (f32, f32), dead_zone: (f32, f32)) -> Camera {
+        Camera { x: 0.0, y: 0.0, view, dead_zone }
+    }
+
+    /// Puts `target` in the middle of the view straight away.
+    pub fn center_on(&mut self, target: &Position, bounds: (f32, f32)) {
+        self.x = target.x + (target.w - self.view.0) / 2.0;
+        self.y = target.y + (target.h - self.view.1) / 2.0;
+        self.clamp(bounds);
+    }
+
+    /// Moves just enough to bring `target` back inside the dead zone, then
+    /// keeps the view on a map `bounds` big.
+    pub fn follow(&mut self, target: &Position, bounds: (f32, f32)) {
+        self.x = follow_axis(self.x, self.view.0, self.dead_zone.0, target.x, target.w);
+        self.y = follow_axis(self.y, self.view.1, self.dead_zone.1, target.y, target.h);
+        self.clamp(bounds);
+    }
+
+    /// Maps smaller than the view sit in the middle of it.
+    fn clamp(&mut self, bounds: (f32, f32)) {
+        self.x = clamp_axis(self.x, self.view.0, bounds.0);
+        self.y = clamp_axis(self.y, self.view.1, bounds.1);
+    }
+
+    pub fn to_screen(&self, pos: Position) -> Position {
+        Position { x: pos.x - self.x, y: pos.y - self.y, ..pos }
+    }
+}
+
+fn follow_axis(camera: f32, view: f32, dead_zone: f32, target: f32, size: f32) -> f32 {
+    let zone_start = camera + (view - dead_zone) / 2.0;
+    let zone_end = zone_start + dead_zone;
+    if target < zone_start {
+        camera - (zone_start - target)
+    } else if target + size > zone_end {
+        camera + (target + size - zone_end)
+    } else {
+        camera
+    }
+}
+
+fn clamp_axis(camera: f32, view: f32, bound: f32) -> f32 {
+    if bound <= view {
+        (bound - view) / 2.0
+    } else {
+        camera.max(0.0).min(bound - view)
+    }
+}
@@ -0,0 +1,94 @@
+//! Asking the server to let us into a game, with a page up while it answers.
+
+use ggez::event::{KeyCode, KeyMods};
+use ggez::{Context, GameResult};
+
+use std::rc::Rc;
+
+use crate::connection::{Connection, Pending};
+use crate::lobby::{self, Join};
+use crate::protocol::Message;
+use super::menu::draw_page;
+use super::room::Room;
+use super::scene::{Assets, Scene, Transition};
+
+pub struct Connecting {
+    connection: Rc<Connection>,
+    player_name: String,
+    join: Join,
+    /// The request waiting on the server, creating the game and then joining it for `Join::Create`
+    request: Option<Pending>,
+    /// Why the server turned us away, once it has
+    status: String,
+}
+
+impl Connecting {
+    pub fn new(connection: Rc<Connection>, player_name: String, join: Join) -> Connecting {
+        Connecting { connection, player_name, join, request: None, status: String::new() }
+    }
+
+    fn target(&self) -> String {
+        match &self.join {
+            Join::Game(game_id) => format!("game {}", game_id),
+            Join::Quick => "the fullest open game".to_string(),
+            Join::Create(_) => "a new game".to_string(),
+            Join::Rejoin(_) => "your game again".to_string(),
+        }
+    }
+}
+
+impl Scene for Connecting {
+    fn update(&mut self, _ctx: &mut Context, _assets: &Assets) -> GameResult<Transition> {
+        if !self.status.is_empty() {
+            return Ok(Transition::None)
+        }
+        let result = match &self.request {
+            None => {
+                let msg = lobby::request(&self.player_name, &self.join);
+                self.request = Some(self.connection.start(msg, lobby::timeout()));
+                return Ok(Transition::None)
+            },
+            Some(request) => match request.poll(&self.connection) {
+                Some(result) => result,
+                None => return Ok(Transition::None),
+            },
+        };
+        let joined = match result {
+            Ok(Message::GameCreated { game_id }) => {
+                let msg = Message::JoinGame { game_id, player: self.player_name.clone() };
+                self.request = Some(self.connection.start(msg, lobby::timeout()));
+                return Ok(Transition::None)
+            },
+            Ok(reply) => lobby::joined(reply),
+            Err(e) => Err(e),
+        };
+        match joined {
+            Ok(joined) => {
+                let world = joined.world;
+                println!("{} joined game {} on {}, rejoin with session {}", joined.player, world.game_id, world.map, joined.session);
+                let room = Room::new(self.connection.clone(), joined.player, joined.session, world);
+                Ok(Transition::Replace(Box::new(room)))
+            },
+            Err(e) => {
+                self.status = format!("Could not join: {}", e);
+                Ok(Transition::None)
+            },
+        }
+    }
+
+    fn draw(&mut self, ctx: &mut Context) -> GameResult {
+        let mut lines = vec![(format!("Joining {}...", self.target()), false)];
+        if !self.status.is_empty() {
+            lines.push((String::new(), false));
+            lines.push(("Escape to go back".to_string(), false));
+        }
+        draw_page(ctx, &lines, &self.status)
+    }
+
+    fn key_down(&mut self, _ctx: &mut Context, keycode: KeyCode, _keymod: KeyMods, _repeat: bool) -> Transition {
+        match keycode {
+            KeyCode::Escape => Transition::Pop(1),
+            _ => Transition::None,
+        }
+    }
+}
@@ -0,0 +1,157 @@
+//! Drawn over a game once the server has gone quiet, trying to rejoin
+//! until the server answers or the player gives up. Attempts never block,
+//! the answer is looked for every frame so the window keeps responding.
+
+use ggez::event::{KeyCode, KeyMods};
+use ggez::{Context, GameResult};
+
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use crate::connection::{Connection, RequestError};
+use crate::protocol::Message;
+use crate::shared::{NET_HEARTBEAT_MILLIS, NET_REQUEST_TIMEOUT_MILLIS, NET_TIMEOUT_MILLIS};
+use super::hud::Hud;
+use super::scene::{Assets, Scene, Transition};
+
+/// Wait between attempts to rejoin, on top of the time each one takes
+const NET_REJOIN_RETRY_MILLIS: u64 = 1000;
+
+/// Keeps the server hearing from a scene that's in a game, and notices
+/// when we stop hearing from the server.
+pub struct Heartbeat {
+    session: String,
+    last_sent: Instant,
+    last_heard: Instant,
+    /// Set while the connection lost overlay is up
+    lost: bool,
+}
+
+impl Heartbeat {
+    pub fn new(session: String) -> Heartbeat {
+        Heartbeat { session, last_sent: Instant::now(), last_heard: Instant::now(), lost: false }
+    }
+
+    /// Sends a heartbeat when one is due. `heard` is whether anything came
+    /// in since the last check, once nothing has for `NET_TIMEOUT_MILLIS`
+    /// this puts up the connection lost overlay.
+    pub fn check(&mut self, connection: &Rc<Connection>, heard: bool) -> Transition {
+        if self.last_sent.elapsed() >= Duration::from_millis(NET_HEARTBEAT_MILLIS) {
+            let _ = connection.send(&Message::Heartbeat { session: self.session.clone() });
+            self.last_sent = Instant::now();
+        }
+        if heard {
+            self.last_heard = Instant::now();
+        }
+        if self.lost || self.last_heard.elapsed() < Duration::from_millis(NET_TIMEOUT_MILLIS) {
+            return Transition::None
+        }
+        println!("Lost the connection to the server");
+        self.lost = true;
+        Transition::Push(Box::new(ConnectionLost::new(connection.clone(), self.session.clone())))
+    }
+
+    /// Starts watching again once the overlay is gone.
+    pub fn reset(&mut self) {
+        self.last_heard = Instant::now();
+        self.lost = false;
+    }
+}
+
+pub struct ConnectionLost {
+    connection: Rc<Connection>,
+    session: String,
+    last_attempt: Instant,
+    /// Sequence number of the rejoin waiting on an answer
+    attempt: Option<u32>,
+    /// Why the server turned us away, after which there's no point retrying
+    refused: Option<String>,
+    hud: Hud,
+}
+
+impl ConnectionLost {
+    pub fn new(connection: Rc<Connection>, session: String) -> ConnectionLost {
+        ConnectionLost {
+            connection,
+            session,
+            last_attempt: Instant::now(),
+            attempt: None,
+            refused: None,
+            hud: Hud::new(),
+        }
+    }
+
+    /// Sends another rejoin once the last one has been given up on.
+    fn try_rejoin(&mut self) {
+        if self.last_attempt.elapsed() < Duration::from_millis(NET_REJOIN_RETRY_MILLIS) {
+            return
+        }
+        self.last_attempt = Instant::now();
+        match self.connection.send_reliable(Message::Rejoin { session: self.session.clone() }) {
+            Ok(seq) => self.attempt = Some(seq),
+            Err(e) => println!("Could not send rejoin: {}", e),
+        }
+    }
+}
+
+impl Scene for ConnectionLost {
+    fn update(&mut self, _ctx: &mut Context, _assets: &Assets) -> GameResult<Transition> {
+        if self.refused.is_some() {
+            return Ok(Transition::None)
+        }
+        let seq = match self.attempt {
+            Some(seq) => seq,
+            None => {
+                self.try_rejoin();
+                return Ok(Transition::None)
+            },
+        };
+        match self.connection.reply_to(seq) {
+            Some(Ok(Message::Joined { world, .. })) => {
+                println!("Back in game {}", world.game_id);
+                return Ok(Transition::Pop(1))
+            },
+            Some(Err(RequestError::Refused(reason))) => {
+                println!("Could not rejoin: {}", reason);
+                self.refused = Some(reason);
+            },
+            Some(_) => self.attempt = None,
+            None => {
+                // Given up on, a reply to it that turns up later is thrown away
+                if self.last_attempt.elapsed() >= Duration::from_millis(NET_REQUEST_TIMEOUT_MILLIS) {
+                    self.attempt = None;
+                    self.last_attempt = Instant::now();
+                }
+            },
+        }
+        Ok(Transition::None)
+    }
+
+    fn draw(&mut self, ctx: &mut Context) -> GameResult {
+        let lines = match &self.refused {
+            Some(reason) => vec![
+                "Connection lost".to_string(),
+                reason.clone(),
+                "Escape to go back to the games".to_string(),
+            ],
+            None => vec![
+                "Connection lost".to_string(),
+                "Trying to get back in...".to_string(),
+                "Escape to give up".to_string(),
+            ],
+        };
+        self.hud.draw_banner(ctx, &lines)
+    }
+
+    fn key_down(&mut self, _ctx: &mut Context, keycode: KeyCode, _keymod: KeyMods, _repeat: bool) -> Transition {
+        match keycode {
+            // The game under us goes too
+            KeyCode::Escape => Transition::Pop(2),
+            _ => Transition::None,
+        }
+    }
+
+    fn is_overlay(&self) -> bool {
+        true
+    }
+}
@@ -0,0 +1,218 @@
+//! Stats, inventory and scores around the edge of the window, and the
+//! banners shown over the arena between rounds.
+
+use ggez::{graphics, Context, GameResult};
+use graphics::{GlBackendSpec, ImageGeneric, Rect};
+use glam::*;
+
+use std::collections::HashMap;
+
+use crate::shared::items::Item;
+use crate::shared::{Player, GRID_CELL_SIZE, INVENTORY_SLOTS};
+use super::SCREEN_SIZE;
+
+
+#[derive(Clone)]
+pub struct Hud {
+}
+
+impl Hud {
+
+    pub fn new() -> Hud {
+        Hud {}
+    }
+
+    /// `scoreboard` is every player's name and score, leader first.
+    pub fn draw(
+        &self,
+        ctx: &mut Context,
+        player: &Player,
+        scoreboard: &[(String, u32)],
+        selected_slot: usize,
+        textures: &HashMap<String, ImageGeneric<GlBackendSpec>>,
+    ) -> GameResult<()> {
+        let color = [0.0, 0.0, 0.0, 1.0].into();
+        let top_back = graphics::Rect {
+                x: 0.0,
+                y: 0.0,
+                w: SCREEN_SIZE.0,
+                h: GRID_CELL_SIZE,
+        };
+        let bottom_back = graphics::Rect {
+                x: 0.0,
+                y: SCREEN_SIZE.1 - GRID_CELL_SIZE,
+                w: SCREEN_SIZE.0,
+                h: GRID_CELL_SIZE,
+        };
+        let top_rectangle =
+            graphics::Mesh::new_rectangle(ctx, graphics::DrawMode::fill(), top_back, color)?;
+        graphics::draw(ctx, &top_rectangle, (ggez::mint::Point2 { x: 0.0, y: 0.0 },))?;
+        let bottom_rectangle =
+            graphics::Mesh::new_rectangle(ctx, graphics::DrawMode::fill(), bottom_back, color)?;
+        graphics::draw(ctx, &bottom_rectangle, (ggez::mint::Point2 { x: 0.0, y: 0.0 },))?;
+        let player_name = graphics::Text::new(graphics::TextFragment {
+                text: format!("Player: {}", player.name),
+                color: Some(graphics::Color::new(1.0, 1.0, 1.0, 1.0)),
+                // `Font` is a handle to a loaded TTF, stored inside the `Context`.
+                // `Font::default()` always exists and maps to DejaVuSerif.
+                font: Some(graphics::Font::default()),
+                scale: Some(graphics::PxScale { x: 30.0, y: 30.0 }),
+            });
+        let hp_text = graphics::Text::new(graphics::TextFragment {
+                text: format!("{}", player.hp),
+                color: Some(graphics::Color::new(1.0, 0.2, 0.2, 1.0)),
+                // `Font` is a handle to a loaded TTF, stored inside the `Context`.
+                // `Font::default()` always exists and maps to DejaVuSerif.
+                font: Some(graphics::Font::default()),
+                scale: Some(graphics::PxScale { x: 30.0, y: 30.0 }),
+            });
+        let str_text = graphics::Text::new(graphics::TextFragment {
+                text: format!("{}", player.str),
+                color: Some(graphics::Color::new(1.0, 1.0, 0.2, 1.0)),
+                // `Font` is a handle to a loaded TTF, stored inside the `Context`.
+                // `Font::default()` always exists and maps to DejaVuSerif.
+                font: Some(graphics::Font::default()),
+                scale: Some(graphics::PxScale { x: 30.0, y: 30.0 }),
+            });
+        let mp_text = graphics::Text::new(graphics::TextFragment {
+                text: format!("{}", player.mp),
+                color: Some(graphics::Color::new(0.0, 0.4, 1.0, 1.0)),
+                // `Font` is a handle to a loaded TTF, stored inside the `Context`.
+                // `Font::default()` always exists and maps to DejaVuSerif.
+                font: Some(graphics::Font::default()),
+                scale: Some(graphics::PxScale { x: 30.0, y: 30.0 }),
+            });
+        graphics::queue_text(ctx, &str_text, ggez::mint::Point2 { x: 130.0, y: SCREEN_SIZE.1 - GRID_CELL_SIZE }, None);
+        graphics::queue_text(ctx, &mp_text, ggez::mint::Point2 { x: 70.0, y: SCREEN_SIZE.1 - GRID_CELL_SIZE }, None);
+        graphics::queue_text(ctx, &hp_text, ggez::mint::Point2 { x: 0.0, y: SCREEN_SIZE.1 - GRID_CELL_SIZE }, None);
+        let scores = scoreboard.iter().map(|(name, score)| format!("{} {}", name, score)).collect::<Vec<String>>().join("  ");
+        let score_text = graphics::Text::new(graphics::TextFragment {
+                text: scores,
+                color: Some(graphics::Color::new(1.0, 1.0, 1.0, 1.0)),
+                font: Some(graphics::Font::default()),
+                scale: Some(graphics::PxScale { x: 18.0, y: 18.0 }),
+            });
+        graphics::queue_text(ctx, &player_name, ggez::mint::Point2 { x: 0.0, y: 0.0 }, None);
+        graphics::queue_text(ctx, &score_text, ggez::mint::Point2 { x: SCREEN_SIZE.0 / 2.0, y: 7.0 }, None);
+        graphics::draw_queued_text(
+                ctx,
+                graphics::DrawParam::new()
+                    .dest(ggez::mint::Point2 { x: 0.0, y: 0.0}),
+                    //.rotation(-0.5),
+                None,
+                graphics::FilterMode::Linear,
+            )?;
+        self.draw_inventory(ctx, player, selected_slot, textures)
+    }
+
+    /// Right hand end of the bottom bar: weapon, armor, then the numbered
+    /// inventory slots with the selected one outlined.
+    pub fn draw_inventory(
+        &self,
+        ctx: &mut Context,
+        player: &Player,
+        selected_slot: usize,
+        textures: &HashMap<String, ImageGeneric<GlBackendSpec>>,
+    ) -> GameResult<()> {
+        let size = GRID_CELL_SIZE - 4.0;
+        let y = SCREEN_SIZE.1 - GRID_CELL_SIZE + 2.0;
+        let first_x = SCREEN_SIZE.0 - (INVENTORY_SLOTS + 2) as f32 * GRID_CELL_SIZE - GRID_CELL_SIZE / 2.0;
+        let mut boxes = vec![(first_x, "W".to_string(), &player.weapon), (first_x + GRID_CELL_SIZE, "A".to_string(), &player.armor)];
+        for (index, slot) in player.inventory.iter().enumerate() {
+            let x = first_x + (index + 2) as f32 * GRID_CELL_SIZE + GRID_CELL_SIZE / 2.0;
+            boxes.push((x, format!("{}", index + 1), slot));
+        }
+
+        for (index, (x, label, item)) in boxes.iter().enumerate() {
+            let slot_rect = Rect::new(*x, y, size, size);
+            let slot_back =
+                graphics::Mesh::new_rectangle(ctx, graphics::DrawMode::fill(), slot_rect, [0.25, 0.25, 0.25, 1.0].into())?;
+            graphics::draw(ctx, &slot_back, (ggez::mint::Point2 { x: 0.0, y: 0.0 },))?;
+            if let Some(item) = item {
+                self.draw_item_icon(ctx, item, textures, slot_rect)?;
+            }
+            if index == selected_slot + 2 {
+                let outline =
+                    graphics::Mesh::new_rectangle(ctx, graphics::DrawMode::stroke(2.0), slot_rect, [1.0, 1.0, 0.2, 1.0].into())?;
+                graphics::draw(ctx, &outline, (ggez::mint::Point2 { x: 0.0, y: 0.0 },))?;
+            }
+            let label_text = graphics::Text::new(graphics::TextFragment {
+                    text: label.clone(),
+                    color: Some(graphics::Color::new(1.0, 1.0, 1.0, 1.0)),
+                    font: Some(graphics::Font::default()),
+                    scale: Some(graphics::PxScale { x: 12.0, y: 12.0 }),
+                });
+            graphics::queue_text(ctx, &label_text, ggez::mint::Point2 { x: x + 2.0, y }, None);
+        }
+        graphics::draw_queued_text(
+                ctx,
+                graphics::DrawParam::new()
+                    .dest(ggez::mint::Point2 { x: 0.0, y: 0.0}),
+                None,
+                graphics::FilterMode::Linear,
+            )?;
+        Ok(())
+    }
+
+    /// `item`'s sprite shrunk to fit inside `rect`.
+    pub fn draw_item_icon(
+        &self,
+        ctx: &mut Context,
+        item: &Item,
+        textures: &HashMap<String, ImageGeneric<GlBackendSpec>>,
+        rect: Rect,
+    ) -> GameResult<()> {
+        let sprite = &item.sprite;
+        if let Some(texture) = sprite.texture.as_ref().and_then(|name| textures.get(name)) {
+            let scale = (rect.w / (texture.width() as f32 * sprite.src[2]))
+                .min(rect.h / (texture.height() as f32 * sprite.src[3]));
+            let param = graphics::DrawParam::new()
+            .src(graphics::Rect {x: sprite.src[0], y: sprite.src[1], w: sprite.src[2], h: sprite.src[3]})
+            .dest(Vec2::new(rect.x, rect.y))
+            .scale(Vec2::new(scale, scale));
+            graphics::draw(ctx, texture, param)?;
+        } else {
+            let inset = Rect::new(rect.x + 6.0, rect.y + 6.0, rect.w - 12.0, rect.h - 12.0);
+            let icon = graphics::Mesh::new_rectangle(ctx, graphics::DrawMode::fill(), inset, sprite.color.into())?;
+            graphics::draw(ctx, &icon, (ggez::mint::Point2 { x: 0.0, y: 0.0 },))?;
+        }
+        Ok(())
+    }
+
+    /// Centered box of text over the arena, used for the countdown and results.
+    pub fn draw_banner(&self, ctx: &mut Context, lines: &[String]) -> GameResult<()> {
+        if lines.is_empty() {
+            return Ok(())
+        }
+        let line_height = 30.0;
+        let height = line_height * lines.len() as f32 + GRID_CELL_SIZE;
+        let back = graphics::Rect {
+                x: GRID_CELL_SIZE * 2.0,
+                y: (SCREEN_SIZE.1 - height) / 2.0,
+                w: SCREEN_SIZE.0 - GRID_CELL_SIZE * 4.0,
+                h: height,
+        };
+        let back_rectangle =
+            graphics::Mesh::new_rectangle(ctx, graphics::DrawMode::fill(), back, [0.0, 0.0, 0.0, 0.7].into())?;
+        graphics::draw(ctx, &back_rectangle, (ggez::mint::Point2 { x: 0.0, y: 0.0 },))?;
+        for (index, line) in lines.iter().enumerate() {
+            let text = graphics::Text::new(graphics::TextFragment {
+                    text: line.clone(),
+                    color: Some(graphics::Color::new(1.0, 1.0, 1.0, 1.0)),
+                    font: Some(graphics::Font::default()),
+                    scale: Some(graphics::PxScale { x: 24.0, y: 24.0 }),
+                });
+            let x = (SCREEN_SIZE.0 - text.width(ctx)) / 2.0;
+            let y = back.y + GRID_CELL_SIZE / 2.0 + line_height * index as f32;
+            graphics::queue_text(ctx, &text, ggez::mint::Point2 { x, y }, None);
+        }
+        graphics::draw_queued_text(
+                ctx,
+                graphics::DrawParam::new()
+                    .dest(ggez::mint::Point2 { x: 0.0, y: 0.0}),
+                None,
+                graphics::FilterMode::Linear,
+            )?;
+        Ok(())
+    }
+}
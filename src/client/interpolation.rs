@@ -0,0 +1,109 @@
+//! Renders remote players slightly in the past so there is almost always a
+//! pair of server snapshots to blend between.
+//!
+//! Snapshots are stamped with the server's game clock. We keep a running
+//! estimate of how far ahead of our own clock the server is, render at
+//! `server now - INTERPOLATION_DELAY_MILLIS`, and linearly interpolate each
+//! remote player between the two snapshots either side of that time. If the
+//! next snapshot is late we keep going along the last known velocity, but
+//! only for `MAX_EXTRAPOLATION_MILLIS` before holding still.
+
+use std::collections::VecDeque;
+use std::time::Instant;
+
+use crate::protocol::{PlayerState, WorldSnapshot};
+
+const INTERPOLATION_DELAY_MILLIS: f64 = 100.0;
+const MAX_EXTRAPOLATION_MILLIS: f64 = 200.0;
+const SNAPSHOT_HISTORY_MILLIS: f64 = 1000.0;
+/// How much of each new clock sample is folded into the offset estimate
+const CLOCK_SMOOTHING: f64 = 0.1;
+
+pub struct SnapshotBuffer {
+    snapshots: VecDeque<WorldSnapshot>,
+    /// Estimated server clock minus our clock, in milliseconds
+    clock_offset: Option<f64>,
+    epoch: Instant,
+}
+
+impl SnapshotBuffer {
+    pub fn new() -> SnapshotBuffer {
+        SnapshotBuffer {
+            snapshots: VecDeque::new(),
+            clock_offset: None,
+            epoch: Instant::now(),
+        }
+    }
+
+    fn local_millis(&self) -> f64 {
+        self.epoch.elapsed().as_secs_f64() * 1000.0
+    }
+
+    pub fn push(&mut self, snapshot: WorldSnapshot) {
+        // UDP can reorder, anything older than what we have is useless
+        if let Some(newest) = self.snapshots.back() {
+            if snapshot.server_time <= newest.server_time {
+                return;
+            }
+        }
+
+        let sample = snapshot.server_time as f64 - self.local_millis();
+        self.clock_offset = Some(match self.clock_offset {
+            Some(offset) => offset + (sample - offset) * CLOCK_SMOOTHING,
+            None => sample,
+        });
+
+        let oldest_kept = snapshot.server_time as f64 - SNAPSHOT_HISTORY_MILLIS;
+        self.snapshots.push_back(snapshot);
+        while self.snapshots.len() > 2 && (self.snapshots[0].server_time as f64) < oldest_kept {
+            self.snapshots.pop_front();
+        }
+    }
+
+    pub fn latest(&self) -> Option<&WorldSnapshot> {
+        self.snapshots.back()
+    }
+
+    /// Where the named player should be drawn right now.
+    pub fn sample(&self, name: &str) -> Option<PlayerState> {
+        let render_time = self.local_millis() + self.clock_offset? - INTERPOLATION_DELAY_MILLIS;
+        let history: Vec<(f64, &PlayerState)> = self.snapshots.iter()
+            .filter_map(|s| s.players.iter().find(|p| p.name == name).map(|p| (s.server_time as f64, p)))
+            .collect();
+
+        let (oldest_time, oldest) = *history.first()?;
+        if render_time <= oldest_time {
+            return Some(oldest.clone());
+        }
+        for pair in history.windows(2) {
+            let (from_time, from) = pair[0];
+            let (to_time, to) = pair[1];
+            if render_time <= to_time {
+                let t = ((render_time - from_time) / (to_time - from_time)) as f32;
+                return Some(blend(from, to, t));
+            }
+        }
+
+        // Past the newest snapshot, carry on along the last velocity for a little while
+        let (newest_time, newest) = *history.last()?;
+        if history.len() < 2 {
+            return Some(newest.clone());
+        }
+        let (previous_time, previous) = history[history.len() - 2];
+        let ahead = (render_time - newest_time).min(MAX_EXTRAPOLATION_MILLIS);
+        let t = 1.0 + (ahead / (newest_time - previous_time)) as f32;
+        Some(blend(previous, newest, t))
+    }
+}
+
+/// Continuous values move from `from` towards `to` (`t` of 0 to 1, or beyond
+/// to extrapolate), everything else is taken from whichever is nearer.
+fn blend(from: &PlayerState, to: &PlayerState, t: f32) -> PlayerState {
+    let mut state = if t < 0.5 { from.clone() } else { to.clone() };
+    state.body.x = from.body.x + (to.body.x - from.body.x) * t;
+    state.body.y = from.body.y + (to.body.y - from.body.y) * t;
+    if from.jumping && to.jumping {
+        state.jump_time = from.jump_time + (to.jump_time - from.jump_time) * t.min(1.0);
+    }
+    state
+}
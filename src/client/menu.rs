@@ -0,0 +1,343 @@
+//! The first scene: picking a name and a server, then browsing and
+//! creating games. Joining one hands over to the connecting scene.
+
+use ggez::event::{KeyCode, KeyMods};
+use ggez::{graphics, Context, GameResult};
+
+use std::rc::Rc;
+use std::time::Duration;
+
+use crate::connection::{Connection, Pending};
+use crate::lobby::{self, GameSettings, Join};
+use crate::protocol::{GameInfo, Message};
+use crate::server::{DEFAULT_BEST_OF, DEFAULT_MAX_PLAYERS, MAX_BEST_OF, MAX_PLAYERS, MIN_PLAYERS};
+use crate::shared::{GRID_CELL_SIZE, PLAYER_NAME_MAX_CHARS};
+use super::connecting::Connecting;
+use super::scene::{Assets, Scene, Transition};
+use super::SCREEN_SIZE;
+
+const MENU_LINE_HEIGHT: f32 = 28.0;
+/// Longest server address, game name or map name that can be typed in
+const MENU_MAX_INPUT_CHARS: usize = 32;
+/// Lines of the create page, in the order they're shown
+const CREATE_FIELDS: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Page {
+    Name,
+    Server,
+    Games,
+    Create,
+}
+
+pub struct Menu {
+    page: Page,
+    player_name: String,
+    host: String,
+    connection: Option<Rc<Connection>>,
+    games: Vec<GameInfo>,
+    ping: Duration,
+    /// The game list asked for, polled until the server answers
+    listing: Option<Pending>,
+    /// Highlighted game on the games page, or field on the create page
+    selected: usize,
+    settings: GameSettings,
+    /// Game to join as soon as we're connected, from the command line
+    join: Option<Join>,
+    /// What went wrong last, shown at the bottom until the next action
+    status: String,
+}
+
+fn menu_text(line: &str, size: f32, color: graphics::Color) -> graphics::Text {
+    graphics::Text::new(graphics::TextFragment {
+        text: line.to_string(),
+        color: Some(color),
+        font: Some(graphics::Font::default()),
+        scale: Some(graphics::PxScale { x: size, y: size }),
+    })
+}
+
+/// Draws a full page of the menus: the title, `lines` with the highlighted
+/// ones picked out, and `status` along the bottom.
+pub fn draw_page(ctx: &mut Context, lines: &[(String, bool)], status: &str) -> GameResult {
+    graphics::clear(ctx, [0.1, 0.1, 0.15, 1.0].into());
+    let white = graphics::Color::new(1.0, 1.0, 1.0, 1.0);
+    let yellow = graphics::Color::new(1.0, 1.0, 0.2, 1.0);
+    let title = menu_text("Item Wars!", 48.0, yellow);
+    let x = (SCREEN_SIZE.0 - title.width(ctx)) / 2.0;
+    graphics::queue_text(ctx, &title, ggez::mint::Point2 { x, y: GRID_CELL_SIZE }, None);
+    for (index, (line, highlighted)) in lines.iter().enumerate() {
+        let color = if *highlighted { yellow } else { white };
+        let prefix = if *highlighted { "> " } else { "  " };
+        let text = menu_text(&format!("{}{}", prefix, line), 22.0, color);
+        let y = GRID_CELL_SIZE * 4.0 + MENU_LINE_HEIGHT * index as f32;
+        graphics::queue_text(ctx, &text, ggez::mint::Point2 { x: GRID_CELL_SIZE, y }, None);
+    }
+    if !status.is_empty() {
+        let text = menu_text(status, 18.0, graphics::Color::new(1.0, 0.3, 0.3, 1.0));
+        let y = SCREEN_SIZE.1 - GRID_CELL_SIZE * 1.5;
+        graphics::queue_text(ctx, &text, ggez::mint::Point2 { x: GRID_CELL_SIZE, y }, None);
+    }
+    graphics::draw_queued_text(
+            ctx,
+            graphics::DrawParam::new()
+                .dest(ggez::mint::Point2 { x: 0.0, y: 0.0}),
+            None,
+            graphics::FilterMode::Linear,
+        )
+}
+
+/// Typed characters `page` takes, names are kept to what the server accepts.
+fn accepts(page: Page, field: usize, ch: char) -> bool {
+    match page {
+        Page::Name => ch.is_alphanumeric(),
+        Page::Server => ch.is_alphanumeric() || ch == '.' || ch == ':' || ch == '-',
+        Page::Create if field == 0 => ch.is_alphanumeric() || ch == ' ',
+        Page::Create if field == 3 => ch.is_alphanumeric() || ch == '_' || ch == '-',
+        _ => false,
+    }
+}
+
+impl Menu {
+    /// Starts on the name page, or connects and joins the game `join`
+    /// picks straight away when one was given on the command line.
+    pub fn new(player_name: String, host: String, join: Option<Join>) -> Menu {
+        let mut menu = Menu {
+            page: Page::Name,
+            player_name,
+            host,
+            connection: None,
+            games: vec![],
+            ping: Duration::from_millis(0),
+            listing: None,
+            selected: 0,
+            settings: GameSettings {
+                name: String::new(),
+                max_players: DEFAULT_MAX_PLAYERS,
+                best_of: DEFAULT_BEST_OF,
+                map: String::new(),
+            },
+            join: None,
+            status: String::new(),
+        };
+        if join.is_some() && menu.connect() {
+            menu.join = join;
+        }
+        menu
+    }
+
+    fn connect(&mut self) -> bool {
+        match Connection::connect(&self.host) {
+            Ok(connection) => {
+                self.connection = Some(Rc::new(connection));
+                self.refresh();
+                true
+            },
+            Err(e) => {
+                self.status = format!("Could not connect to {}: {}", self.host, e);
+                false
+            },
+        }
+    }
+
+    /// Asks for the game list again, `update` picks up the answer.
+    fn refresh(&mut self) {
+        self.page = Page::Games;
+        if let Some(connection) = &self.connection {
+            self.listing = Some(connection.start(Message::ListGames, lobby::timeout()));
+        }
+    }
+
+    fn check_listing(&mut self) {
+        let (connection, listing) = match (&self.connection, &self.listing) {
+            (Some(connection), Some(listing)) => (connection, listing),
+            _ => return,
+        };
+        let result = match listing.poll(connection) {
+            Some(result) => result.and_then(lobby::game_list),
+            None => return,
+        };
+        match result {
+            Ok(games) => {
+                self.games = games;
+                self.ping = listing.elapsed();
+                self.selected = self.selected.min(self.games.len().saturating_sub(1));
+                self.status.clear();
+            },
+            Err(e) => self.status = format!("Could not list games: {}", e),
+        }
+        self.listing = None;
+    }
+
+    fn enter(&self, join: Join) -> Transition {
+        match &self.connection {
+            Some(connection) => Transition::Push(Box::new(Connecting::new(connection.clone(), self.player_name.clone(), join))),
+            None => Transition::None,
+        }
+    }
+
+    /// The create page's field `field` moved `step` notches.
+    fn adjust(&mut self, field: usize, step: i8) {
+        match field {
+            1 => {
+                let players = self.settings.max_players as i8 + step;
+                self.settings.max_players = players.max(MIN_PLAYERS as i8).min(MAX_PLAYERS as i8) as u8;
+            },
+            2 => {
+                // Best of an odd number so a round can't end the match tied
+                let rounds = self.settings.best_of as i8 + step * 2;
+                self.settings.best_of = rounds.max(1).min(MAX_BEST_OF as i8) as u8;
+            },
+            _ => (),
+        }
+    }
+
+    /// The text being typed into on this page, if it takes any.
+    fn input(&mut self) -> Option<&mut String> {
+        match self.page {
+            Page::Name => Some(&mut self.player_name),
+            Page::Server => Some(&mut self.host),
+            Page::Create if self.selected == 0 => Some(&mut self.settings.name),
+            Page::Create if self.selected == 3 => Some(&mut self.settings.map),
+            _ => None,
+        }
+    }
+
+    fn lines(&self) -> Vec<(String, bool)> {
+        let mut lines = vec![];
+        match self.page {
+            Page::Name => {
+                lines.push(("Your name:".to_string(), false));
+                lines.push((format!("{}_", self.player_name), true));
+                lines.push((String::new(), false));
+                lines.push(("Enter to go on, Escape to quit".to_string(), false));
+            },
+            Page::Server => {
+                lines.push(("Server:".to_string(), false));
+                lines.push((format!("{}_", self.host), true));
+                lines.push((String::new(), false));
+                lines.push(("Enter to connect, Escape to go back".to_string(), false));
+            },
+            Page::Games => {
+                lines.push((format!("Games on {} (ping {} ms)", self.host, self.ping.as_millis()), false));
+                if self.games.is_empty() {
+                    let line = if self.listing.is_some() { "Looking for games..." } else { "No open games" };
+                    lines.push((line.to_string(), false));
+                }
+                for (index, game) in self.games.iter().enumerate() {
+                    lines.push((
+                        format!(
+                            "{:<16} {:<10} {}/{} best of {}",
+                            game.name, game.map, game.players, game.max_players, game.best_of,
+                        ),
+                        index == self.selected,
+                    ));
+                }
+                lines.push((String::new(), false));
+                lines.push(("Enter join, Q quick join, C create".to_string(), false));
+                lines.push(("R refresh, Escape change server".to_string(), false));
+            },
+            Page::Create => {
+                let name = if self.settings.name.is_empty() { "(any)" } else { &self.settings.name };
+                let map = if self.settings.map.is_empty() { "(server default)" } else { &self.settings.map };
+                lines.push(("New game".to_string(), false));
+                lines.push((format!("Name: {}", name), self.selected == 0));
+                lines.push((format!("Players: < {} >", self.settings.max_players), self.selected == 1));
+                lines.push((format!("Best of: < {} >", self.settings.best_of), self.selected == 2));
+                lines.push((format!("Map: {}", map), self.selected == 3));
+                lines.push((String::new(), false));
+                lines.push(("Enter to create, Escape to go back".to_string(), false));
+            },
+        }
+        lines
+    }
+}
+
+impl Scene for Menu {
+    fn update(&mut self, _ctx: &mut Context, _assets: &Assets) -> GameResult<Transition> {
+        if let Some(join) = self.join.take() {
+            return Ok(self.enter(join))
+        }
+        if let Some(reason) = self.connection.as_ref().and_then(|connection| connection.closed()) {
+            self.connection = None;
+            self.page = Page::Server;
+            self.status = format!("Server shut down: {}", reason);
+        }
+        self.check_listing();
+        Ok(Transition::None)
+    }
+
+    fn draw(&mut self, ctx: &mut Context) -> GameResult {
+        draw_page(ctx, &self.lines(), &self.status)
+    }
+
+    fn text_input(&mut self, _ctx: &mut Context, character: char) -> Transition {
+        let (page, field) = (self.page, self.selected);
+        let max_chars = if page == Page::Name { PLAYER_NAME_MAX_CHARS } else { MENU_MAX_INPUT_CHARS };
+        if let Some(input) = self.input() {
+            if accepts(page, field, character) && input.chars().count() < max_chars {
+                input.push(character);
+            }
+        }
+        Transition::None
+    }
+
+    fn key_down(&mut self, _ctx: &mut Context, keycode: KeyCode, _keymod: KeyMods, _repeat: bool) -> Transition {
+        if keycode == KeyCode::Back {
+            if let Some(input) = self.input() {
+                input.pop();
+            }
+            return Transition::None
+        }
+        match (self.page, keycode) {
+            (Page::Name, KeyCode::Return) => {
+                if self.player_name.is_empty() {
+                    self.status = "Pick a name first".to_string();
+                } else {
+                    self.status.clear();
+                    self.page = Page::Server;
+                }
+            },
+            (Page::Name, KeyCode::Escape) => return Transition::Quit,
+            (Page::Server, KeyCode::Return) => {
+                self.connect();
+            },
+            (Page::Server, KeyCode::Escape) => self.page = Page::Name,
+            (Page::Games, KeyCode::Up) => self.selected = self.selected.saturating_sub(1),
+            (Page::Games, KeyCode::Down) => {
+                self.selected = (self.selected + 1).min(self.games.len().saturating_sub(1))
+            },
+            (Page::Games, KeyCode::Return) => {
+                if let Some(game) = self.games.get(self.selected) {
+                    return self.enter(Join::Game(game.game_id.clone()))
+                }
+            },
+            (Page::Games, KeyCode::Q) => return self.enter(Join::Quick),
+            (Page::Games, KeyCode::C) => {
+                // Not on the name, the C would land in it
+                self.selected = 1;
+                self.page = Page::Create;
+            },
+            (Page::Games, KeyCode::R) => self.refresh(),
+            (Page::Games, KeyCode::Escape) => {
+                self.connection = None;
+                self.page = Page::Server;
+            },
+            (Page::Create, KeyCode::Up) => self.selected = self.selected.saturating_sub(1),
+            (Page::Create, KeyCode::Down) => self.selected = (self.selected + 1).min(CREATE_FIELDS - 1),
+            (Page::Create, KeyCode::Left) => self.adjust(self.selected, -1),
+            (Page::Create, KeyCode::Right) => self.adjust(self.selected, 1),
+            (Page::Create, KeyCode::Return) => return self.enter(Join::Create(self.settings.clone())),
+            (Page::Create, KeyCode::Escape) => self.refresh(),
+            _ => (),
+        }
+        Transition::None
+    }
+
+    /// Back from a game or a failed join, the list has likely changed.
+    fn resume(&mut self) {
+        if self.connection.as_ref().is_some_and(|connection| connection.closed().is_none()) {
+            self.refresh();
+        }
+    }
+}
@@ -0,0 +1,721 @@
+//! The game window: a stack of scenes from the menus to the arena, where
+//! we predict our own player and interpolate everyone else. Only built
+//! with the `client` feature.
+
+pub mod camera;
+mod connecting;
+mod connection_lost;
+mod hud;
+mod interpolation;
+mod menu;
+mod pause;
+mod results;
+mod room;
+mod scene;
+
+use ggez::event::{KeyCode, KeyMods};
+use ggez::{event, graphics, Context, GameResult};
+use graphics::{GlBackendSpec, ImageGeneric, Rect};
+use graphics::spritebatch::SpriteBatch;
+use glam::*;
+
+use std::collections::{HashMap, VecDeque};
+use std::env;
+use std::path;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use crate::connection::Connection;
+use crate::lobby::Join;
+use crate::protocol::{Message, PlayerState, WorldSnapshot};
+use crate::shared::collision::Solids;
+use crate::shared::items::{Item, ItemCatalog};
+use crate::shared::map::{Tile, TileMap};
+use crate::shared::load_item_catalog;
+use crate::shared::{Direction, MatchPhase, Pickup, Player, PlayerInput, Position, Projectile};
+use crate::shared::{
+    ATTACK_COOLDOWN_STEPS, ATTACK_SWING_STEPS, GRID_CELL_SIZE,
+    PLAYER_CELL_HEIGHT, PLAYER_CELL_WIDTH, SIM_STEP_NANOS,
+};
+use camera::Camera;
+use connection_lost::Heartbeat;
+use hud::Hud;
+use interpolation::SnapshotBuffer;
+use menu::Menu;
+use pause::Pause;
+use results::Results;
+use scene::{Assets, Scene, SceneStack, Transition};
+
+pub const SCREEN_SIZE: (f32, f32) = (640.0, 480.0);
+/// The camera stays put while the player is inside this box in the middle of the window
+const CAMERA_DEAD_ZONE: (f32, f32) = (160.0, 120.0);
+
+/// Steps the client will run in one frame to catch up, the rest of a stall is dropped
+const SIM_MAX_STEPS_PER_FRAME: u32 = 5;
+const NET_MAX_UNACKED_INPUTS: usize = 120;
+/// Fraction of a prediction error still shown on the next frame
+const PREDICTION_CORRECTION_DECAY: f32 = 0.85;
+/// Errors bigger than this are snapped rather than smoothed
+const PREDICTION_SNAP_DISTANCE: f32 = GRID_CELL_SIZE * 4.0;
+
+impl From<Position> for Rect {
+    fn from(pos: Position) -> Self {
+        Rect { x: pos.x, y: pos.y, w: pos.w, h: pos.h }
+    }
+}
+
+/// The texture named by `item`'s sprite, if it has one and it's loaded.
+fn item_texture<'a>(
+    item: &Item,
+    textures: &'a HashMap<String, ImageGeneric<GlBackendSpec>>,
+) -> Option<&'a ImageGeneric<GlBackendSpec>> {
+    item.sprite.texture.as_ref().and_then(|name| textures.get(name))
+}
+
+/// Draws `item` at `pos` on the screen from its sprite, or as a plain box if it has no art yet.
+fn draw_item(ctx: &mut Context, item: &Item, texture: Option<&ImageGeneric<GlBackendSpec>>, pos: Position) -> GameResult<()> {
+    let sprite = &item.sprite;
+    if let Some(texture) = texture {
+        let param = graphics::DrawParam::new()
+        .src(graphics::Rect {x: sprite.src[0], y: sprite.src[1], w: sprite.src[2], h: sprite.src[3]})
+        .dest(Vec2::new(pos.x, pos.y))
+        .scale(Vec2::new(sprite.scale, sprite.scale));
+        graphics::draw(ctx, texture, param)?;
+    } else {
+        let rectangle =
+            graphics::Mesh::new_rectangle(ctx, graphics::DrawMode::fill(), pos.into(), sprite.color.into())?;
+        graphics::draw(ctx, &rectangle, (ggez::mint::Point2 { x: 0.0, y: 0.0 },))?;
+    }
+    Ok(())
+}
+
+fn draw_pickup(
+    ctx: &mut Context,
+    pickup: &Pickup,
+    textures: &HashMap<String, ImageGeneric<GlBackendSpec>>,
+    camera: &Camera,
+) -> GameResult<()> {
+    draw_item(ctx, &pickup.item, item_texture(&pickup.item, textures), camera.to_screen(pickup.pos))
+}
+
+/// Thrown items are drawn as themselves, spells as a glowing orb.
+fn draw_projectile(
+    ctx: &mut Context,
+    projectile: &Projectile,
+    textures: &HashMap<String, ImageGeneric<GlBackendSpec>>,
+    camera: &Camera,
+) -> GameResult<()> {
+    let body = camera.to_screen(projectile.body);
+    if let Some(item) = &projectile.item {
+        return draw_item(ctx, item, item_texture(item, textures), body);
+    }
+    let radius = body.w / 2.0;
+    let orb = graphics::Mesh::new_circle(
+        ctx,
+        graphics::DrawMode::fill(),
+        ggez::mint::Point2 { x: body.x + radius, y: body.y + radius },
+        radius,
+        0.5,
+        graphics::Color::new(0.3, 0.6, 1.0, 0.9),
+    )?;
+    graphics::draw(ctx, &orb, (ggez::mint::Point2 { x: 0.0, y: 0.0 },))
+}
+
+fn draw_player(
+    ctx: &mut Context,
+    player: &mut Player,
+    texture: Option<&ImageGeneric<GlBackendSpec>>,
+    camera: &Camera,
+) -> GameResult<()> {
+    let body = camera.to_screen(player.drawn_body());
+    // And then we do the same for the head, instead making it fully red to distinguish it.
+    //let bounding_box_rectangle = graphics::Mesh::new_rectangle(
+    //    ctx,
+    //    graphics::DrawMode::fill(),
+    //    player.body.into(),
+    //    [1.0, 0.5, 0.0, 1.0].into(),
+    //)?;
+    //graphics::draw(ctx, &bounding_box_rectangle, (ggez::mint::Point2 { x: 0.0, y: 0.0 },))?;
+    //let black_rectangle = graphics::Mesh::new_rectangle(
+    //    ctx,
+    //    graphics::DrawMode::fill(),
+    //    Rect::new(player.body.x, player.body.y, player.body.w, player.body.h),
+    //    [0.0, 0.0, 0.0, 1.0].into(),
+    //)?;
+    //graphics::draw(ctx, &black_rectangle, (ggez::mint::Point2 { x: 0.0, y: 0.0 },))?;
+
+    if player.jumping {
+        let bounding_box_rectangle = graphics::Mesh::new_circle(
+            ctx,
+            graphics::DrawMode::fill(),
+            ggez::mint::Point2 { x: body.x + 15.0,  y: body.y + 47.0 },
+            14.0,
+            1.0,
+            graphics::Color::new(0.0, 0.0, 0.0, 0.3),
+        )?;
+        graphics::draw(ctx, &bounding_box_rectangle, (ggez::mint::Point2 { x: 0.0, y: 0.0 },))?;
+    }
+
+    if player.attack_cooldown > ATTACK_COOLDOWN_STEPS - ATTACK_SWING_STEPS {
+        let hitbox = player.attack_hitbox();
+        let hitbox = camera.to_screen(Position { x: hitbox.x + player.draw_offset.0, y: hitbox.y + player.draw_offset.1, ..hitbox });
+        let swing_rectangle = graphics::Mesh::new_rectangle(
+            ctx,
+            graphics::DrawMode::fill(),
+            hitbox.into(),
+            graphics::Color::new(1.0, 1.0, 1.0, 0.3),
+        )?;
+        graphics::draw(ctx, &swing_rectangle, (ggez::mint::Point2 { x: 0.0, y: 0.0 },))?;
+    }
+
+    let black_rectangle = graphics::Mesh::new_rectangle(
+        ctx,
+        graphics::DrawMode::fill(),
+        Rect::new(body.x - 13.0, body.y - 45.0, 60.0, 35.0),
+        [0.0, 0.0, 0.0, 1.0].into(),
+    )?;
+    graphics::draw(ctx, &black_rectangle, (ggez::mint::Point2 { x: 0.0, y: 0.0 },))?;
+
+    let player_name = graphics::Text::new(graphics::TextFragment {
+        text: player.name.clone(),
+        color: Some(graphics::Color::new(1.0, 1.0, 1.0, 1.0)),
+        // `Font` is a handle to a loaded TTF, stored inside the `Context`.
+        // `Font::default()` always exists and maps to DejaVuSerif.
+        font: Some(graphics::Font::default()),
+        scale: Some(graphics::PxScale { x: 15.0, y: 15.0 }),
+    });
+    let player_hp = graphics::Text::new(graphics::TextFragment {
+        text: format!("{}", player.hp),
+        color: Some(graphics::Color::new(0.9, 0.0, 0.0, 1.0)),
+        // `Font` is a handle to a loaded TTF, stored inside the `Context`.
+        // `Font::default()` always exists and maps to DejaVuSerif.
+        font: Some(graphics::Font::default()),
+        scale: Some(graphics::PxScale { x: 15.0, y: 15.0 }),
+    });
+    let player_mp = graphics::Text::new(graphics::TextFragment {
+        text: format!("{}", player.mp),
+        color: Some(graphics::Color::new(0.0, 0.4, 1.0, 1.0)),
+        // `Font` is a handle to a loaded TTF, stored inside the `Context`.
+        // `Font::default()` always exists and maps to DejaVuSerif.
+        font: Some(graphics::Font::default()),
+        scale: Some(graphics::PxScale { x: 15.0, y: 15.0 }),
+    });
+    graphics::queue_text(ctx, &player_name, ggez::mint::Point2 { x: body.x - (player.name.chars().count() as f32) + 5.0, y: body.y - GRID_CELL_SIZE - 10.0 }, None);
+    graphics::queue_text(ctx, &player_hp, ggez::mint::Point2 { x: body.x - (GRID_CELL_SIZE / 2.0) + 5.0, y: body.y - GRID_CELL_SIZE + 5.0 }, None);
+    graphics::queue_text(ctx, &player_mp, ggez::mint::Point2 { x: body.x - (GRID_CELL_SIZE / 2.0) + 45.0, y: body.y - GRID_CELL_SIZE + 5.0 }, None);
+    graphics::draw_queued_text(
+        ctx,
+        graphics::DrawParam::new()
+            .dest(ggez::mint::Point2 { x: 0.0, y: 0.0}),
+            //.rotation(-0.5),
+        None,
+        graphics::FilterMode::Linear,
+    )?;
+    player.animate_frames();
+    // Faded once knocked out, red while knocked back, blinking while nothing can hit us
+    let tint = if player.hp <= 0 {
+        graphics::Color::new(0.5, 0.5, 0.5, 0.5)
+    } else if player.is_hit {
+        graphics::Color::new(1.0, 0.3, 0.3, 1.0)
+    } else if player.invulnerable > 0 && (player.invulnerable / 4) % 2 == 1 {
+        graphics::Color::new(1.0, 1.0, 1.0, 0.4)
+    } else {
+        graphics::Color::new(1.0, 1.0, 1.0, 1.0)
+    };
+    let param = graphics::DrawParam::new()
+    .src(graphics::Rect {x: player.animation_frame, y: player.get_animation_direction(), w: 0.25, h: 0.25})
+    .dest(Vec2::new(body.x + 2.0, body.y - 10.0))
+    .offset(Vec2::new(0.15, player.jump_offset()))
+    .scale(Vec2::new(0.1, 0.1))
+    .color(tint);
+    //.rotation((time % cycle) as f32 / cycle as f32 * 6.28)
+    //.offset(Vec2::new(150.0, 150.0));
+    if let Some(player_texture) = texture {
+        graphics::draw(ctx, player_texture, param)?;
+    }
+    Ok(())
+}
+
+/// Where the world's origin ends up on screen.
+fn camera_origin(camera: &Camera) -> ggez::mint::Point2<f32> {
+    ggez::mint::Point2 { x: -camera.x, y: -camera.y }
+}
+
+/// Every floor tile as its own piece of the background texture, which
+/// repeats on maps bigger than it.
+fn build_map_floor(map: &TileMap, background: &ImageGeneric<GlBackendSpec>) -> SpriteBatch {
+    let columns = (background.width() as f32 / GRID_CELL_SIZE).max(1.0) as usize;
+    let rows = (background.height() as f32 / GRID_CELL_SIZE).max(1.0) as usize;
+    let mut batch = SpriteBatch::new(background.clone());
+    for (x, y, tile) in map.tiles() {
+        if tile == Tile::Wall {
+            continue;
+        }
+        let cell = map.tile_rect(x, y);
+        let param = graphics::DrawParam::new()
+        .src(Rect {
+            x: (x % columns) as f32 / columns as f32,
+            y: (y % rows) as f32 / rows as f32,
+            w: 1.0 / columns as f32,
+            h: 1.0 / rows as f32,
+        })
+        .dest(Vec2::new(cell.x, cell.y));
+        batch.add(param);
+    }
+    batch
+}
+
+/// Walls, obstacles and hazards drawn over the floor. None if the map has none.
+fn build_map_mesh(ctx: &mut Context, map: &TileMap) -> GameResult<Option<graphics::Mesh>> {
+    let mut builder = graphics::MeshBuilder::new();
+    let mut empty = true;
+    for (x, y, tile) in map.tiles() {
+        let color = match tile {
+            Tile::Wall => graphics::Color::new(0.25, 0.22, 0.2, 1.0),
+            Tile::Low => graphics::Color::new(0.55, 0.4, 0.25, 1.0),
+            Tile::Hazard => graphics::Color::new(0.9, 0.35, 0.05, 0.85),
+            Tile::Floor => continue,
+        };
+        builder.rectangle(graphics::DrawMode::fill(), map.tile_rect(x, y).into(), color)?;
+        empty = false;
+    }
+    if empty {
+        return Ok(None);
+    }
+    builder.build(ctx).map(Some)
+}
+
+pub struct GameState {
+    player: Player,
+    /// Everyone else in the match, as the server last showed them
+    remote_players: Vec<Player>,
+    /// Items in the arena, as of the latest snapshot
+    pickups: Vec<Pickup>,
+    /// Projectiles in flight, as of the latest snapshot
+    projectiles: Vec<Projectile>,
+    catalog: ItemCatalog,
+    map: TileMap,
+    /// Floor, walls and hazards, built on the first draw
+    map_floor: Option<SpriteBatch>,
+    map_mesh: Option<graphics::Mesh>,
+    camera: Camera,
+    connection: Rc<Connection>,
+    game_id: String,
+    /// Token the server gave us on joining, sent with everything we do
+    session: String,
+    heartbeat: Heartbeat,
+    /// Phase of the latest snapshot
+    phase: MatchPhase,
+    last_sim_update: Instant,
+    /// Wall time not yet simulated, run off in fixed steps like the server does
+    sim_accumulator: Duration,
+    /// Direction keys currently held down
+    held_dir: Direction,
+    jump_pressed: bool,
+    attack_pressed: bool,
+    cast_pressed: bool,
+    /// Inventory slot the use and drop keys act on
+    selected_slot: usize,
+    input_seq: u32,
+    /// Inputs we've predicted locally that the server hasn't acknowledged
+    unacked_inputs: VecDeque<PlayerInput>,
+    last_reconciled_time: u64,
+    hud: Hud,
+    textures: HashMap<String, graphics::ImageGeneric<GlBackendSpec>>,
+    snapshots: SnapshotBuffer,
+}
+
+impl GameState {
+
+    /// Using and dropping are sent reliably and only happen once the server
+    /// agrees, the next snapshot shows the result.
+    fn send_use_item(connection: &Connection, session: String, slot: usize) {
+        let _ = connection.send_reliable(Message::UseItem { session, slot: slot as u8 });
+    }
+
+    fn send_drop_item(connection: &Connection, session: String, slot: usize) {
+        let _ = connection.send_reliable(Message::DropItem { session, slot: slot as u8 });
+    }
+
+    fn send_input(connection: &Connection, session: String, input: PlayerInput) {
+        let _ = connection.send(&Message::Input { session, input });
+    }
+
+    /// Rewinds our player to the server's state and replays every input the
+    /// server hasn't simulated yet. Whatever moved is eased out via `draw_offset`.
+    fn reconcile(&mut self, server_player: &PlayerState) {
+        let drawn_at = (self.player.body.x + self.player.draw_offset.0, self.player.body.y + self.player.draw_offset.1);
+
+        self.unacked_inputs.retain(|input| input.seq > server_player.last_input_seq);
+        self.player.sync_from_server(server_player, &self.catalog);
+        let bodies = self.remote_bodies();
+        let solids = Solids { map: &self.map, bodies: &bodies };
+        for input in self.unacked_inputs.iter() {
+            self.player.step(Some(input), &solids);
+        }
+
+        let error = (drawn_at.0 - self.player.body.x, drawn_at.1 - self.player.body.y);
+        if error.0.hypot(error.1) > PREDICTION_SNAP_DISTANCE {
+            self.player.draw_offset = (0.0, 0.0);
+        } else {
+            self.player.draw_offset = error;
+        }
+    }
+
+    /// Where the other players standing in our way are drawn, which is as
+    /// close as we can get to where the server has them.
+    fn remote_bodies(&self) -> Vec<Position> {
+        self.remote_players.iter().filter(|p| p.hp > 0).map(|p| p.body).collect()
+    }
+
+    /// Adds and drops remote players to match the latest snapshot, then
+    /// moves each one to its interpolated position.
+    fn sync_remote_players(&mut self) {
+        let names: Vec<String> = match self.snapshots.latest() {
+            Some(world) => world.players.iter().map(|p| p.name.clone()).filter(|name| *name != self.player.name).collect(),
+            None => return,
+        };
+        self.remote_players.retain(|p| names.contains(&p.name));
+        for name in names {
+            if !self.remote_players.iter().any(|p| p.name == name) {
+                let pos = Position { x: 0.0, y: 0.0, w: PLAYER_CELL_WIDTH, h: PLAYER_CELL_HEIGHT };
+                self.remote_players.push(Player::new(name, pos));
+            }
+        }
+        for remote in self.remote_players.iter_mut() {
+            if let Some(server_remote) = self.snapshots.sample(&remote.name) {
+                remote.sync_from_server(&server_remote, &self.catalog);
+                remote.dir = server_remote.dir;
+            }
+        }
+    }
+
+    /// Inputs only count while playing, anything the server hasn't simulated
+    /// by the end of a round never will be.
+    fn change_phase(&mut self, phase: MatchPhase) {
+        match phase {
+            MatchPhase::Countdown => println!("Get ready!"),
+            MatchPhase::Playing => println!("Fight!"),
+            MatchPhase::RoundOver => println!("Round over"),
+            MatchPhase::Finished => println!("Game over"),
+            MatchPhase::Lobby => (),
+        }
+        if phase != MatchPhase::Playing {
+            self.unacked_inputs.clear();
+            self.player.dir = Direction::default();
+        }
+        self.phase = phase;
+    }
+
+    /// What to tell the player over the arena, if anything.
+    fn banner(&self) -> Vec<String> {
+        let world = match self.snapshots.latest() {
+            Some(world) => world,
+            None => return vec![],
+        };
+        let missing: Vec<&str> = world.players.iter().filter(|p| !p.connected).map(|p| p.name.as_str()).collect();
+        if !missing.is_empty() && world.phase != MatchPhase::Finished {
+            return vec![
+                format!("Lost contact with {}", missing.join(", ")),
+                "Waiting for them to come back".to_string(),
+            ]
+        }
+        match world.phase {
+            MatchPhase::Countdown => vec![
+                format!("Round {} of {}", world.round, world.best_of),
+                format!("{}", world.phase_millis.div_ceil(1000)),
+            ],
+            MatchPhase::Playing if self.player.hp <= 0 => vec![
+                "Knocked out!".to_string(),
+                "Waiting for the round to end".to_string(),
+            ],
+            // The match hands over to the results scene once it's finished
+            MatchPhase::Lobby | MatchPhase::Playing | MatchPhase::Finished => vec![],
+            MatchPhase::RoundOver => {
+                let mut standing = world.players.iter().filter(|p| p.hp > 0);
+                match (standing.next(), standing.next()) {
+                    (Some(winner), None) => vec![format!("{} takes round {}", winner.name, world.round)],
+                    _ => vec![format!("Round {} is a draw", world.round)],
+                }
+            },
+        }
+    }
+
+    /// Items don't move, so the latest snapshot is all we need. Anything
+    /// missing from our copy of the catalog is left out.
+    fn sync_pickups(&mut self) {
+        if let Some(world) = self.snapshots.latest() {
+            let catalog = &self.catalog;
+            self.pickups = world.pickups.iter()
+                .filter_map(|p| Some(Pickup::new(p.pos, catalog.get(&p.item_id)?.clone())))
+                .collect();
+        }
+    }
+
+    fn sync_projectiles(&mut self) {
+        if let Some(world) = self.snapshots.latest() {
+            let catalog = &self.catalog;
+            self.projectiles = world.projectiles.iter()
+                .map(|p| {
+                    let item = p.item_id.as_ref().and_then(|id| catalog.get(id)).cloned();
+                    Projectile::new(p.owner.clone(), p.body, p.dir.clone(), 0, item)
+                })
+                .collect();
+        }
+    }
+
+    /// `game_state` is the world the match started with.
+    pub fn new(
+        player_name: String,
+        connection: Rc<Connection>,
+        session: String,
+        game_state: WorldSnapshot,
+        catalog: ItemCatalog,
+        map: TileMap,
+        textures: HashMap<String, graphics::ImageGeneric<GlBackendSpec>>,
+    ) -> Self {
+        let game_id = game_state.game_id.clone();
+        let mut player_pos = Position { x: 100.0, y: 100.0, w: PLAYER_CELL_WIDTH, h: PLAYER_CELL_HEIGHT };
+        if let Some(game_state_player) = game_state.players.iter().find(|p| p.name == player_name) {
+            player_pos.x = game_state_player.body.x;
+            player_pos.y = game_state_player.body.y;
+        }
+        let player = Player::new(player_name, player_pos);
+        let mut camera = Camera::new(SCREEN_SIZE, CAMERA_DEAD_ZONE);
+        camera.center_on(&player.body, map.pixel_size());
+        let mut snapshots = SnapshotBuffer::new();
+        snapshots.push(game_state);
+
+        GameState {
+            player,
+            remote_players: vec![],
+            connection,
+            game_id,
+            heartbeat: Heartbeat::new(session.clone()),
+            session,
+            pickups: vec![],
+            projectiles: vec![],
+            catalog,
+            map,
+            map_floor: None,
+            map_mesh: None,
+            camera,
+            hud: Hud::new(),
+            phase: MatchPhase::Lobby,
+            last_sim_update: Instant::now(),
+            sim_accumulator: Duration::from_millis(0),
+            held_dir: Direction::default(),
+            jump_pressed: false,
+            attack_pressed: false,
+            cast_pressed: false,
+            selected_slot: 0,
+            input_seq: 0,
+            unacked_inputs: VecDeque::new(),
+            last_reconciled_time: 0,
+            textures,
+            snapshots,
+        }
+    }
+}
+
+impl Scene for GameState {
+    fn update(&mut self, _ctx: &mut Context, _assets: &Assets) -> GameResult<Transition> {
+        // The menu says why once we're back on it
+        if self.connection.closed().is_some() {
+            return Ok(Transition::Pop(1))
+        }
+        let snapshots = self.connection.snapshots();
+        let heard = !snapshots.is_empty();
+        for snapshot in snapshots {
+            self.snapshots.push(snapshot);
+        }
+        let transition = self.heartbeat.check(&self.connection, heard);
+        if !matches!(transition, Transition::None) {
+            return Ok(transition)
+        }
+
+        let phase = match self.snapshots.latest() {
+            Some(world) => world.phase,
+            None => MatchPhase::Lobby,
+        };
+        if phase != self.phase {
+            self.change_phase(phase);
+        }
+        if self.phase == MatchPhase::Finished {
+            if let Some(world) = self.snapshots.latest() {
+                return Ok(Transition::Replace(Box::new(Results::new(world, &self.player.name))))
+            }
+        }
+
+        // We predict ourselves, correcting against each new snapshot, and
+        // show everyone else a little in the past
+        let server_player = match self.snapshots.latest() {
+            Some(world) if world.server_time > self.last_reconciled_time => {
+                self.last_reconciled_time = world.server_time;
+                world.players.iter().find(|p| p.name == self.player.name).cloned()
+            },
+            _ => None,
+        };
+        if let Some(server_player) = server_player {
+            self.reconcile(&server_player);
+        }
+        self.sync_remote_players();
+        self.sync_pickups();
+        self.sync_projectiles();
+
+        // Move straight away and tell the server what we did
+        let step = Duration::from_nanos(SIM_STEP_NANOS);
+        let now = Instant::now();
+        self.sim_accumulator = (self.sim_accumulator + (now - self.last_sim_update)).min(step * SIM_MAX_STEPS_PER_FRAME);
+        self.last_sim_update = now;
+        while self.sim_accumulator >= step {
+            if self.phase == MatchPhase::Playing && self.player.hp > 0 {
+                self.input_seq += 1;
+                let input = PlayerInput {
+                    seq: self.input_seq,
+                    dir: self.held_dir.clone(),
+                    jump: self.jump_pressed,
+                    attack: self.attack_pressed,
+                    cast: self.cast_pressed,
+                };
+                self.jump_pressed = false;
+                self.attack_pressed = false;
+                self.cast_pressed = false;
+                let bodies = self.remote_bodies();
+                self.player.step(Some(&input), &Solids { map: &self.map, bodies: &bodies });
+                GameState::send_input(&self.connection, self.session.clone(), input.clone());
+                self.unacked_inputs.push_back(input);
+                while self.unacked_inputs.len() > NET_MAX_UNACKED_INPUTS {
+                    self.unacked_inputs.pop_front();
+                }
+            }
+            self.player.draw_offset.0 *= PREDICTION_CORRECTION_DECAY;
+            self.player.draw_offset.1 *= PREDICTION_CORRECTION_DECAY;
+            self.sim_accumulator -= step;
+        }
+        Ok(Transition::None)
+    }
+
+    fn draw(&mut self, ctx: &mut Context) -> GameResult {
+        graphics::clear(ctx, [0.0, 0.5, 0.0, 1.0].into());
+        if self.map_floor.is_none() {
+            self.map_floor = Some(build_map_floor(&self.map, self.textures.get("background").unwrap()));
+            self.map_mesh = build_map_mesh(ctx, &self.map)?;
+        }
+        self.camera.follow(&self.player.drawn_body(), self.map.pixel_size());
+        // The map is built in world coordinates, moving it by the camera puts it on screen
+        let map_origin = camera_origin(&self.camera);
+        if let Some(floor) = &self.map_floor {
+            graphics::draw(ctx, floor, (map_origin,))?;
+        }
+        if let Some(mesh) = &self.map_mesh {
+            graphics::draw(ctx, mesh, (map_origin,))?;
+        }
+
+        // Then we tell the player and the items to draw themselves
+        for pickup in self.pickups.iter() {
+            draw_pickup(ctx, pickup, &self.textures, &self.camera)?;
+        }
+        for projectile in self.projectiles.iter() {
+            draw_projectile(ctx, projectile, &self.textures, &self.camera)?;
+        }
+        for remote in self.remote_players.iter_mut() {
+            draw_player(ctx, remote, self.textures.get("hero"), &self.camera)?;
+        }
+        draw_player(ctx, &mut self.player, self.textures.get("hero"), &self.camera)?;
+        let mut scoreboard: Vec<(String, u32)> = self.remote_players.iter().chain(std::iter::once(&self.player))
+            .map(|p| (p.name.clone(), p.score)).collect();
+        scoreboard.sort_by_key(|entry| std::cmp::Reverse(entry.1));
+        self.hud.draw(ctx, &self.player, &scoreboard, self.selected_slot, &self.textures)?;
+        self.hud.draw_banner(ctx, &self.banner())
+    }
+
+    fn key_up(
+        &mut self,
+        _ctx: &mut Context,
+        keycode: KeyCode,
+        _keymod: KeyMods,
+    ) -> Transition {
+        match keycode {
+            KeyCode::A => self.held_dir.left = false,
+            KeyCode::D => self.held_dir.right = false,
+            KeyCode::W => self.held_dir.up = false,
+            KeyCode::S => self.held_dir.down = false,
+            _ => ()
+        };
+        Transition::None
+    }
+
+    /// key_down gets fired when a key gets pressed.
+    fn key_down(
+        &mut self,
+        _ctx: &mut Context,
+        keycode: KeyCode,
+        _keymod: KeyMods,
+        _repeat: bool,
+    ) -> Transition {
+        match keycode {
+            KeyCode::Escape => {
+                // The pause overlay gets the key ups from here on
+                self.held_dir = Direction::default();
+                let pause = Pause::new(self.connection.clone(), self.session.clone(), self.game_id.clone());
+                return Transition::Push(Box::new(pause))
+            },
+            KeyCode::A => self.held_dir.left = true,
+            KeyCode::D => self.held_dir.right = true,
+            KeyCode::W => self.held_dir.up = true,
+            KeyCode::S => self.held_dir.down = true,
+            KeyCode::Space if !self.player.jumping => self.jump_pressed = true,
+            KeyCode::LAlt => self.attack_pressed = true,
+            KeyCode::F => self.cast_pressed = true,
+            KeyCode::Key1 => self.selected_slot = 0,
+            KeyCode::Key2 => self.selected_slot = 1,
+            KeyCode::Key3 => self.selected_slot = 2,
+            KeyCode::Key4 => self.selected_slot = 3,
+            KeyCode::E => GameState::send_use_item(&self.connection, self.session.clone(), self.selected_slot),
+            KeyCode::Q => GameState::send_drop_item(&self.connection, self.session.clone(), self.selected_slot),
+            _ => ()
+        };
+        Transition::None
+    }
+
+    /// Back from the pause or connection lost overlay.
+    fn resume(&mut self) {
+        self.heartbeat.reset();
+    }
+}
+
+/// Opens the window on the menus, filled in with `player_name` and `host`.
+/// With a `join` it goes straight to that game's lobby instead.
+pub fn run(player_name: String, host: String, join: Option<Join>, items: Option<&str>, maps: Option<&str>) -> GameResult {
+    let resource_dir = if let Ok(manifest_dir) = env::var("CARGO_MANIFEST_DIR") {
+        let mut path = path::PathBuf::from(manifest_dir);
+        path.push("textures");
+        path
+    } else {
+        path::PathBuf::from("./textures")
+    };
+
+    let (mut ctx, events_loop) = ggez::ContextBuilder::new("iterm wars", "Mitt Miles")
+        .window_setup(ggez::conf::WindowSetup::default().title("Item Wars!"))
+        .window_mode(ggez::conf::WindowMode::default().dimensions(SCREEN_SIZE.0, SCREEN_SIZE.1))
+        .add_resource_path(resource_dir)
+        .build()?;
+    // To enable fullscreen
+    //graphics::set_fullscreen(&mut ctx, ggez::conf::FullscreenType::True).unwrap();
+
+    // Load our textures
+    let mut textures: HashMap<String, ImageGeneric<GlBackendSpec>> = HashMap::new();
+    textures.insert("background".to_string(), graphics::Image::new(&mut ctx, "/tile.png").unwrap());
+    textures.insert("hero".to_string(), graphics::Image::new(&mut ctx, "/hero.png").unwrap());
+    textures.insert("potion".to_string(), graphics::Image::new(&mut ctx, "/potion.png").unwrap());
+
+    let assets = Assets {
+        catalog: load_item_catalog(items),
+        maps: maps.map(|dir| dir.to_string()),
+        textures,
+    };
+    let scenes = SceneStack::new(Box::new(Menu::new(player_name, host, join)), assets);
+    // And finally we actually run our game, passing in our context and state.
+    event::run(ctx, events_loop, scenes)
+}
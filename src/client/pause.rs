@@ -0,0 +1,73 @@
+//! Drawn over the match while paused. The match carries on underneath,
+//! the server doesn't stop for one player.
+
+use ggez::event::{KeyCode, KeyMods};
+use ggez::{Context, GameResult};
+
+use std::rc::Rc;
+
+use crate::connection::{Connection, Pending};
+use crate::lobby;
+use crate::protocol::Message;
+use super::hud::Hud;
+use super::scene::{Assets, Scene, Transition};
+
+pub struct Pause {
+    connection: Rc<Connection>,
+    session: String,
+    game_id: String,
+    /// Leaving, the match closes once the server answers
+    leaving: Option<Pending>,
+    hud: Hud,
+}
+
+impl Pause {
+    pub fn new(connection: Rc<Connection>, session: String, game_id: String) -> Pause {
+        Pause { connection, session, game_id, leaving: None, hud: Hud::new() }
+    }
+}
+
+impl Scene for Pause {
+    fn update(&mut self, _ctx: &mut Context, _assets: &Assets) -> GameResult<Transition> {
+        let result = match self.leaving.as_ref().and_then(|leaving| leaving.poll(&self.connection)) {
+            Some(result) => result,
+            None => return Ok(Transition::None),
+        };
+        if let Err(e) = result {
+            println!("Could not leave game {}: {}", self.game_id, e);
+        }
+        // The match goes too
+        Ok(Transition::Pop(2))
+    }
+
+    fn draw(&mut self, ctx: &mut Context) -> GameResult {
+        if self.leaving.is_some() {
+            return self.hud.draw_banner(ctx, &["Leaving...".to_string()])
+        }
+        let lines = vec![
+            "Paused".to_string(),
+            "Escape to go back to the game".to_string(),
+            "L to leave the game".to_string(),
+        ];
+        self.hud.draw_banner(ctx, &lines)
+    }
+
+    fn key_down(&mut self, _ctx: &mut Context, keycode: KeyCode, _keymod: KeyMods, _repeat: bool) -> Transition {
+        if self.leaving.is_some() {
+            return Transition::None
+        }
+        match keycode {
+            KeyCode::Escape => Transition::Pop(1),
+            KeyCode::L => {
+                let msg = Message::LeaveGame { session: self.session.clone() };
+                self.leaving = Some(self.connection.start(msg, lobby::timeout()));
+                Transition::None
+            },
+            _ => Transition::None,
+        }
+    }
+
+    fn is_overlay(&self) -> bool {
+        true
+    }
+}
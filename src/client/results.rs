@@ -0,0 +1,51 @@
+//! Final standings once a match is over.
+
+use ggez::event::{KeyCode, KeyMods};
+use ggez::{Context, GameResult};
+
+use crate::protocol::{PlayerState, WorldSnapshot};
+use super::menu::draw_page;
+use super::scene::{Assets, Scene, Transition};
+
+pub struct Results {
+    lines: Vec<(String, bool)>,
+}
+
+impl Results {
+    /// `world` is the last one of the match, `player_name` is picked out in the standings.
+    pub fn new(world: &WorldSnapshot, player_name: &str) -> Results {
+        let mut standings: Vec<&PlayerState> = world.players.iter().collect();
+        // Stable, so a tie keeps join order just like the server's pick of the winner
+        standings.sort_by_key(|p| std::cmp::Reverse((p.round_wins, p.score)));
+        let mut lines = vec![];
+        if let Some(winner) = standings.first() {
+            lines.push((format!("{} wins the match!", winner.name), false));
+        }
+        for player in standings {
+            lines.push((
+                format!("{:<10} {} rounds  {} KOs", player.name, player.round_wins, player.score),
+                player.name == player_name,
+            ));
+        }
+        lines.push((String::new(), false));
+        lines.push(("Enter to go back to the games".to_string(), false));
+        Results { lines }
+    }
+}
+
+impl Scene for Results {
+    fn update(&mut self, _ctx: &mut Context, _assets: &Assets) -> GameResult<Transition> {
+        Ok(Transition::None)
+    }
+
+    fn draw(&mut self, ctx: &mut Context) -> GameResult {
+        draw_page(ctx, &self.lines, "")
+    }
+
+    fn key_down(&mut self, _ctx: &mut Context, keycode: KeyCode, _keymod: KeyMods, _repeat: bool) -> Transition {
+        match keycode {
+            KeyCode::Return | KeyCode::Escape => Transition::Pop(1),
+            _ => Transition::None,
+        }
+    }
+}
@@ -0,0 +1,160 @@
+//! Waiting in a game's lobby: who has joined, who is ready, until the
+//! server starts the match.
+
+use ggez::event::{KeyCode, KeyMods};
+use ggez::{Context, GameResult};
+
+use std::rc::Rc;
+
+use crate::connection::{Connection, Pending};
+use crate::lobby;
+use crate::protocol::{Message, WorldSnapshot};
+use crate::shared::{load_map, MatchPhase};
+use super::connection_lost::Heartbeat;
+use super::menu::draw_page;
+use super::scene::{Assets, Scene, Transition};
+use super::GameState;
+
+pub struct Room {
+    connection: Rc<Connection>,
+    player_name: String,
+    session: String,
+    /// Latest snapshot of the game, the players in it say who's ready
+    world: WorldSnapshot,
+    ready: bool,
+    /// Readying up, until the server answers
+    readying: Option<Pending>,
+    /// Leaving, the room closes once the server answers
+    leaving: Option<Pending>,
+    heartbeat: Heartbeat,
+    /// Set once the match has started on a map we don't have, there's nothing left but to leave
+    missing_map: bool,
+    status: String,
+}
+
+impl Room {
+    /// `world` is the one the server sent back when we joined.
+    pub fn new(connection: Rc<Connection>, player_name: String, session: String, world: WorldSnapshot) -> Room {
+        let heartbeat = Heartbeat::new(session.clone());
+        Room {
+            connection,
+            player_name,
+            session,
+            world,
+            ready: false,
+            readying: None,
+            leaving: None,
+            heartbeat,
+            missing_map: false,
+            status: String::new(),
+        }
+    }
+
+    fn check_ready(&mut self) {
+        let result = match self.readying.as_ref().and_then(|readying| readying.poll(&self.connection)) {
+            Some(result) => result,
+            None => return,
+        };
+        match result {
+            Ok(_) => self.ready = true,
+            Err(e) => self.status = format!("Could not ready up: {}", e),
+        }
+        self.readying = None;
+    }
+}
+
+impl Scene for Room {
+    fn update(&mut self, _ctx: &mut Context, assets: &Assets) -> GameResult<Transition> {
+        if self.connection.closed().is_some() {
+            return Ok(Transition::Pop(1))
+        }
+        if let Some(leaving) = &self.leaving {
+            return Ok(match leaving.poll(&self.connection) {
+                Some(result) => {
+                    if let Err(e) = result {
+                        println!("Could not leave game {}: {}", self.world.game_id, e);
+                    }
+                    Transition::Pop(1)
+                },
+                None => Transition::None,
+            })
+        }
+        self.check_ready();
+        let mut snapshots = self.connection.snapshots();
+        let heard = !snapshots.is_empty();
+        if let Some(world) = snapshots.pop() {
+            self.world = world;
+        }
+        let transition = self.heartbeat.check(&self.connection, heard);
+        if !matches!(transition, Transition::None) {
+            return Ok(transition)
+        }
+        if self.world.phase == MatchPhase::Lobby || self.missing_map {
+            return Ok(Transition::None)
+        }
+        let map = match load_map(assets.maps.as_deref(), &self.world.map) {
+            Ok(map) => map,
+            Err(e) => {
+                println!("Could not load map {}: {}", self.world.map, e);
+                self.status = format!("Could not load map {}: {}", self.world.map, e);
+                self.missing_map = true;
+                return Ok(Transition::None)
+            },
+        };
+        let state = GameState::new(
+            self.player_name.clone(),
+            self.connection.clone(),
+            self.session.clone(),
+            self.world.clone(),
+            assets.catalog.clone(),
+            map,
+            assets.textures.clone(),
+        );
+        Ok(Transition::Replace(Box::new(state)))
+    }
+
+    fn draw(&mut self, ctx: &mut Context) -> GameResult {
+        let world = &self.world;
+        let mut lines = vec![(format!("Game {} on {}, best of {}", world.game_id, world.map, world.best_of), false)];
+        for player in world.players.iter() {
+            let state = if player.ready { "ready" } else { "not ready" };
+            lines.push((format!("{:<10} {}", player.name, state), player.name == self.player_name));
+        }
+        lines.push((String::new(), false));
+        if self.leaving.is_some() {
+            lines.push(("Leaving...".to_string(), false));
+        } else if self.missing_map {
+            lines.push(("The match started without you".to_string(), false));
+        } else if self.ready {
+            lines.push(("Waiting for the others...".to_string(), false));
+        } else {
+            lines.push(("Enter when you're ready".to_string(), false));
+        }
+        lines.push(("Escape to leave".to_string(), false));
+        draw_page(ctx, &lines, &self.status)
+    }
+
+    fn key_down(&mut self, _ctx: &mut Context, keycode: KeyCode, _keymod: KeyMods, _repeat: bool) -> Transition {
+        if self.leaving.is_some() {
+            return Transition::None
+        }
+        match keycode {
+            KeyCode::Return if !self.ready && self.readying.is_none() => {
+                let msg = Message::Ready { session: self.session.clone() };
+                self.readying = Some(self.connection.start(msg, lobby::timeout()));
+            },
+            KeyCode::Escape => {
+                // Waiting on Ready's answer would throw this one's away
+                self.readying = None;
+                let msg = Message::LeaveGame { session: self.session.clone() };
+                self.leaving = Some(self.connection.start(msg, lobby::timeout()));
+            },
+            _ => (),
+        }
+        Transition::None
+    }
+
+    fn resume(&mut self) {
+        self.heartbeat.reset();
+    }
+}
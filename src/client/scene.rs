@@ -0,0 +1,140 @@
+//! The client is a stack of scenes. Only the top one gets input, and each
+//! update or key press can push, pop or swap scenes. Overlays let the
+//! scene under them keep running and show through.
+
+use ggez::event::{KeyCode, KeyMods};
+use ggez::{event, graphics, Context, GameResult};
+use graphics::{GlBackendSpec, ImageGeneric};
+
+use std::collections::HashMap;
+
+use crate::shared::items::ItemCatalog;
+
+/// What a scene wants done to the stack.
+pub enum Transition {
+    None,
+    Push(Box<dyn Scene>),
+    /// Drops this many scenes off the top
+    Pop(usize),
+    Replace(Box<dyn Scene>),
+    Quit,
+}
+
+/// Loaded once at start up, for the scenes that build a match.
+pub struct Assets {
+    pub catalog: ItemCatalog,
+    /// Directory maps are loaded from
+    pub maps: Option<String>,
+    pub textures: HashMap<String, ImageGeneric<GlBackendSpec>>,
+}
+
+pub trait Scene {
+    fn update(&mut self, ctx: &mut Context, assets: &Assets) -> GameResult<Transition>;
+
+    /// Draws without presenting, the stack presents once everything's drawn.
+    fn draw(&mut self, ctx: &mut Context) -> GameResult;
+
+    fn key_down(&mut self, _ctx: &mut Context, _keycode: KeyCode, _keymod: KeyMods, _repeat: bool) -> Transition {
+        Transition::None
+    }
+
+    fn key_up(&mut self, _ctx: &mut Context, _keycode: KeyCode, _keymod: KeyMods) -> Transition {
+        Transition::None
+    }
+
+    fn text_input(&mut self, _ctx: &mut Context, _character: char) -> Transition {
+        Transition::None
+    }
+
+    /// Overlays are drawn over the scene under them, which keeps updating.
+    fn is_overlay(&self) -> bool {
+        false
+    }
+
+    /// Back on top after the scenes over it were popped.
+    fn resume(&mut self) {}
+}
+
+pub struct SceneStack {
+    scenes: Vec<Box<dyn Scene>>,
+    assets: Assets,
+}
+
+impl SceneStack {
+    pub fn new(first: Box<dyn Scene>, assets: Assets) -> SceneStack {
+        SceneStack { scenes: vec![first], assets }
+    }
+
+    /// The lowest scene that still shows, everything from it up is drawn and updated.
+    fn bottom(&self) -> usize {
+        self.scenes.iter().rposition(|scene| !scene.is_overlay()).unwrap_or(0)
+    }
+
+    fn apply(&mut self, ctx: &mut Context, transition: Transition) {
+        match transition {
+            Transition::None => return,
+            Transition::Push(scene) => self.scenes.push(scene),
+            Transition::Pop(count) => {
+                let keep = self.scenes.len().saturating_sub(count);
+                self.scenes.truncate(keep);
+                if let Some(top) = self.scenes.last_mut() {
+                    top.resume();
+                }
+            },
+            Transition::Replace(scene) => {
+                self.scenes.pop();
+                self.scenes.push(scene);
+            },
+            Transition::Quit => self.scenes.clear(),
+        }
+        if self.scenes.is_empty() {
+            event::quit(ctx);
+        }
+    }
+}
+
+impl event::EventHandler for SceneStack {
+    fn update(&mut self, ctx: &mut Context) -> GameResult {
+        // A scene under an overlay that wants a change takes the overlays with it
+        for index in self.bottom()..self.scenes.len() {
+            let transition = self.scenes[index].update(ctx, &self.assets)?;
+            if !matches!(transition, Transition::None) {
+                self.scenes.truncate(index + 1);
+                self.apply(ctx, transition);
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    fn draw(&mut self, ctx: &mut Context) -> GameResult {
+        graphics::clear(ctx, graphics::Color::new(0.0, 0.0, 0.0, 1.0));
+        for index in self.bottom()..self.scenes.len() {
+            self.scenes[index].draw(ctx)?;
+        }
+        graphics::present(ctx)?;
+        ggez::timer::yield_now();
+        Ok(())
+    }
+
+    fn key_down_event(&mut self, ctx: &mut Context, keycode: KeyCode, keymod: KeyMods, repeat: bool) {
+        if let Some(top) = self.scenes.last_mut() {
+            let transition = top.key_down(ctx, keycode, keymod, repeat);
+            self.apply(ctx, transition);
+        }
+    }
+
+    fn key_up_event(&mut self, ctx: &mut Context, keycode: KeyCode, keymod: KeyMods) {
+        if let Some(top) = self.scenes.last_mut() {
+            let transition = top.key_up(ctx, keycode, keymod);
+            self.apply(ctx, transition);
+        }
+    }
+
+    fn text_input_event(&mut self, ctx: &mut Context, character: char) {
+        if let Some(top) = self.scenes.last_mut() {
+            let transition = top.text_input(ctx, character);
+            self.apply(ctx, transition);
+        }
+    }
+}
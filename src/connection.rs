@@ -0,0 +1,298 @@
+//! Long lived client session with the game server.
+//!
+//! A `Connection` owns a single UDP socket for the life of the client so the
+//! server always sees the same source address and can push world snapshots
+//! to us. A background thread reads the socket, acks and retransmits
+//! reliable messages, and sorts what arrives into pushed snapshots and
+//! replies to our own requests. It stops when the connection is dropped.
+//!
+//! Every reply carries the sequence number of the request it answers, so
+//! one that turns up after its request gave up waiting is thrown away
+//! instead of being taken for the answer to the next.
+
+use std::fmt;
+use std::io;
+use std::net::UdpSocket;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use crossbeam_channel::{unbounded, Receiver, RecvTimeoutError, Sender};
+
+use crate::protocol::{Message, WorldSnapshot};
+use crate::reliable::ReliableChannel;
+
+const RECV_BUFFER_SIZE: usize = 65_000;
+const RECV_ERROR_BACKOFF_MILLIS: u64 = 100;
+/// How often the receive thread wakes up to retransmit when nothing arrives
+const RESEND_CHECK_MILLIS: u64 = 50;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RequestError {
+    NoReply,
+    /// The server answered with an `Error`
+    Refused(String),
+    /// The server answered with something that isn't a reply to the request
+    Unexpected,
+}
+
+impl fmt::Display for RequestError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RequestError::NoReply => write!(f, "no response from server"),
+            RequestError::Refused(reason) => write!(f, "{}", reason),
+            RequestError::Unexpected => write!(f, "unexpected reply from server"),
+        }
+    }
+}
+
+impl std::error::Error for RequestError {}
+
+/// A request sent without waiting for its reply, for callers that mustn't
+/// block, like the client's scenes. Polled until it has an answer. Only one
+/// should be waiting at a time, polling throws replies to anything else away.
+pub struct Pending {
+    seq: u32,
+    sent: Instant,
+    timeout: Duration,
+}
+
+impl Pending {
+    /// The reply once it has come in, or `NoReply` once the timeout is up.
+    pub fn poll(&self, connection: &Connection) -> Option<Result<Message, RequestError>> {
+        match connection.reply_to(self.seq) {
+            Some(result) => Some(result),
+            None if self.sent.elapsed() >= self.timeout => Some(Err(RequestError::NoReply)),
+            None => None,
+        }
+    }
+
+    /// Time since the request went out, the ping once it's answered.
+    pub fn elapsed(&self) -> Duration {
+        self.sent.elapsed()
+    }
+}
+
+pub struct Connection {
+    socket: UdpSocket,
+    reliable: Arc<Mutex<ReliableChannel>>,
+    snapshots: Receiver<WorldSnapshot>,
+    /// Replies by the sequence number of the request they answer
+    replies: Receiver<(u32, Message)>,
+    /// Why the server went away, once it has told us it's shutting down
+    closed: Arc<Mutex<Option<String>>>,
+    /// Set on drop to stop the receive thread
+    shutdown: Arc<AtomicBool>,
+    receiver: Option<JoinHandle<()>>,
+}
+
+impl Connection {
+    pub fn connect(server: &str) -> io::Result<Connection> {
+        let socket = UdpSocket::bind("0.0.0.0:0")?;
+        socket.connect(server)?;
+        socket.set_read_timeout(Some(Duration::from_millis(RESEND_CHECK_MILLIS)))?;
+        let recv_socket = socket.try_clone()?;
+        let reliable = Arc::new(Mutex::new(ReliableChannel::new()));
+        let recv_reliable = reliable.clone();
+        let (snapshot_sender, snapshots) = unbounded();
+        let (reply_sender, replies) = unbounded();
+        let closed = Arc::new(Mutex::new(None));
+        let recv_closed = closed.clone();
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let recv_shutdown = shutdown.clone();
+
+        let receiver = std::thread::spawn(move || {
+            let mut buf = [0; RECV_BUFFER_SIZE];
+            while !recv_shutdown.load(Ordering::SeqCst) {
+                for packet in recv_reliable.lock().unwrap().resend_due() {
+                    let _ = recv_socket.send(&packet);
+                }
+                let size = match recv_socket.recv(&mut buf) {
+                    Ok(size) => size,
+                    Err(e) if e.kind() == io::ErrorKind::WouldBlock || e.kind() == io::ErrorKind::TimedOut => continue,
+                    Err(_e) => {
+                        // Usually the server isn't up (yet), don't spin on it
+                        std::thread::sleep(Duration::from_millis(RECV_ERROR_BACKOFF_MILLIS));
+                        continue
+                    }
+                };
+                let msg = match Message::decode(&buf[0..size]) {
+                    Ok(Message::Ack { seq }) => {
+                        recv_reliable.lock().unwrap().ack(seq);
+                        continue
+                    },
+                    Ok(Message::Reliable { seq, msg }) => {
+                        let (ack, msg) = recv_reliable.lock().unwrap().receive(seq, *msg);
+                        let _ = recv_socket.send(&ack);
+                        match msg {
+                            Some(msg) => msg,
+                            None => continue,
+                        }
+                    },
+                    Ok(msg) => msg,
+                    Err(e) => {
+                        println!("Bad packet from server: {}", e);
+                        continue
+                    }
+                };
+                if let Message::ServerShutdown { reason } = msg {
+                    *recv_closed.lock().unwrap() = Some(reason);
+                    continue
+                }
+                if !Connection::dispatch(msg, &snapshot_sender, &reply_sender) {
+                    // The connection was dropped
+                    return;
+                }
+            }
+        });
+
+        Ok(Connection { socket, reliable, snapshots, replies, closed, shutdown, receiver: Some(receiver) })
+    }
+
+    fn dispatch(msg: Message, snapshots: &Sender<WorldSnapshot>, replies: &Sender<(u32, Message)>) -> bool {
+        match msg {
+            Message::Snapshot(world) => snapshots.send(world).is_ok(),
+            Message::Reply { to, msg } => replies.send((to, *msg)).is_ok(),
+            other => {
+                println!("Unexpected message from server: {:?}", other);
+                true
+            },
+        }
+    }
+
+    /// Fire and forget, used for inputs.
+    pub fn send(&self, msg: &Message) -> io::Result<()> {
+        self.socket.send(&msg.encode()).map(|_| ())
+    }
+
+    /// Sent until the server acks it. Returns the sequence number the
+    /// server's reply to it will carry.
+    pub fn send_reliable(&self, msg: Message) -> io::Result<u32> {
+        let (seq, packet) = self.reliable.lock().unwrap().send(msg);
+        self.socket.send(&packet).map(|_| seq)
+    }
+
+    /// Reliably sends `msg` and hands back a `Pending` to poll for the
+    /// reply, rather than waiting on it. A failed send is retried like a
+    /// lost packet.
+    pub fn start(&self, msg: Message, timeout: Duration) -> Pending {
+        let (seq, packet) = self.reliable.lock().unwrap().send(msg);
+        let _ = self.socket.send(&packet);
+        Pending { seq, sent: Instant::now(), timeout }
+    }
+
+    /// The reply to the request sent as `seq`, if it has come in yet.
+    /// Never waits, and throws away replies to anything else.
+    pub fn reply_to(&self, seq: u32) -> Option<Result<Message, RequestError>> {
+        self.replies.try_iter().find(|(to, _)| *to == seq).map(|(_, reply)| Connection::answer(reply))
+    }
+
+    fn answer(reply: Message) -> Result<Message, RequestError> {
+        match reply {
+            Message::Error { reason } => Err(RequestError::Refused(reason)),
+            reply => Ok(reply),
+        }
+    }
+
+    /// Reliably sends `msg` and waits up to `timeout` for the server's reply.
+    /// An `Error` reply is logged and treated as no reply at all.
+    pub fn request(&self, msg: Message, timeout: Duration) -> Option<Message> {
+        match self.ask(msg, timeout) {
+            Ok(reply) => Some(reply),
+            Err(RequestError::Refused(reason)) => {
+                println!("Server error: {}", reason);
+                None
+            },
+            Err(RequestError::NoReply) | Err(RequestError::Unexpected) => None,
+        }
+    }
+
+    /// Like `request`, but hands back why the server refused.
+    pub fn ask(&self, msg: Message, timeout: Duration) -> Result<Message, RequestError> {
+        let seq = self.send_reliable(msg).map_err(|_| RequestError::NoReply)?;
+        let deadline = Instant::now() + timeout;
+        loop {
+            let left = deadline.saturating_duration_since(Instant::now());
+            match self.replies.recv_timeout(left) {
+                Ok((to, reply)) if to == seq => return Connection::answer(reply),
+                // A late reply to an earlier request
+                Ok(_) => continue,
+                Err(RecvTimeoutError::Timeout) | Err(RecvTimeoutError::Disconnected) => return Err(RequestError::NoReply),
+            }
+        }
+    }
+
+    /// The reason the server gave for shutting down, if it has.
+    pub fn closed(&self) -> Option<String> {
+        self.closed.lock().unwrap().clone()
+    }
+
+    /// Every snapshot the server has pushed since the last call, oldest first.
+    pub fn snapshots(&self) -> Vec<WorldSnapshot> {
+        self.snapshots.try_iter().collect()
+    }
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        self.shutdown.store(true, Ordering::SeqCst);
+        if let Some(receiver) = self.receiver.take() {
+            let _ = receiver.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::SocketAddr;
+
+    /// The next reliable request to arrive that isn't a resend of `seen`.
+    fn next_request(server: &UdpSocket, seen: &[u32]) -> (u32, SocketAddr) {
+        let mut buf = [0; RECV_BUFFER_SIZE];
+        loop {
+            let (size, addr) = server.recv_from(&mut buf).unwrap();
+            if let Ok(Message::Reliable { seq, .. }) = Message::decode(&buf[0..size]) {
+                if !seen.contains(&seq) {
+                    return (seq, addr);
+                }
+            }
+        }
+    }
+
+    fn reply(to: u32, game_id: &str) -> Vec<u8> {
+        Message::Reply { to, msg: Box::new(Message::GameCreated { game_id: game_id.to_string() }) }.encode()
+    }
+
+    #[test]
+    fn late_replies_are_not_taken_for_the_next() {
+        let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+        server.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        let connection = Connection::connect(&server.local_addr().unwrap().to_string()).unwrap();
+
+        assert_eq!(connection.ask(Message::ListGames, Duration::from_millis(100)), Err(RequestError::NoReply));
+        let (first, _) = next_request(&server, &[]);
+        let answering = std::thread::spawn(move || {
+            // The first request's answer only turns up once the second is waiting
+            let (second, addr) = next_request(&server, &[first]);
+            server.send_to(&reply(first, "late"), addr).unwrap();
+            server.send_to(&reply(second, "second"), addr).unwrap();
+        });
+        let answer = connection.ask(Message::ListGames, Duration::from_secs(5));
+        assert_eq!(answer, Ok(Message::GameCreated { game_id: "second".to_string() }));
+        answering.join().unwrap();
+    }
+
+    #[test]
+    fn dropping_stops_the_receive_thread() {
+        let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let connection = Connection::connect(&server.local_addr().unwrap().to_string()).unwrap();
+        let (dropped_sender, dropped) = unbounded();
+        std::thread::spawn(move || {
+            drop(connection);
+            let _ = dropped_sender.send(());
+        });
+        assert!(dropped.recv_timeout(Duration::from_secs(2)).is_ok(), "the receive thread is still running");
+    }
+}
@@ -0,0 +1,14 @@
+//! Item Wars: a top down arena brawler played over UDP.
+//!
+//! `shared` holds the simulation both sides run, `server` hosts games and
+//! `client` is the game window, which needs the `client` feature.
+
+pub mod connection;
+pub mod lobby;
+pub mod protocol;
+mod reliable;
+pub mod server;
+pub mod shared;
+
+#[cfg(feature = "client")]
+pub mod client;
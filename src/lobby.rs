@@ -0,0 +1,166 @@
+//! Finding, creating, joining and leaving games from the client side.
+//!
+//! Everything here is a reliable request to the server and waits for its
+//! answer. Whatever the server refuses comes back as
+//! `RequestError::Refused` with its reason, ready to show the player.
+//! The client's scenes can't wait, they send the same requests with
+//! `Connection::start` and read the replies with `game_list`, `game_created`
+//! and `joined`.
+//!
+//! Joining hands back a session token along with the world. Everything
+//! the player does in the game from then on is sent with it.
+
+use std::time::{Duration, Instant};
+
+use crate::connection::{Connection, RequestError};
+use crate::protocol::{GameInfo, Message, WorldSnapshot};
+use crate::shared::NET_REQUEST_TIMEOUT_MILLIS;
+
+/// How a new game is set up. Empty `name` and `map` leave them up to the server.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GameSettings {
+    pub name: String,
+    pub max_players: u8,
+    pub best_of: u8,
+    pub map: String,
+}
+
+/// Which game a player wants to end up in.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Join {
+    Game(String),
+    /// The fullest open game, or a new one
+    Quick,
+    /// A new game, which the player then joins
+    Create(GameSettings),
+    /// Back into the game a session was given for, after losing the connection
+    Rejoin(String),
+}
+
+/// What the server hands back on letting a player into a game.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Joined {
+    pub session: String,
+    /// The name the player is in the game as, after a rejoin whatever they first joined with
+    pub player: String,
+    /// The world as it was on joining
+    pub world: WorldSnapshot,
+}
+
+/// The games in a reply to `ListGames`.
+pub fn game_list(reply: Message) -> Result<Vec<GameInfo>, RequestError> {
+    match reply {
+        Message::GameList { games } => Ok(games),
+        _ => Err(RequestError::Unexpected),
+    }
+}
+
+/// Id of the game in a reply to `NewGame`.
+pub fn game_created(reply: Message) -> Result<String, RequestError> {
+    match reply {
+        Message::GameCreated { game_id } => Ok(game_id),
+        _ => Err(RequestError::Unexpected),
+    }
+}
+
+/// What a reply to joining, quick joining or rejoining let the player into.
+pub fn joined(reply: Message) -> Result<Joined, RequestError> {
+    match reply {
+        Message::Joined { session, player, world } => Ok(Joined { session, player, world }),
+        _ => Err(RequestError::Unexpected),
+    }
+}
+
+/// How long the server gets to answer any of these.
+pub fn timeout() -> Duration {
+    Duration::from_millis(NET_REQUEST_TIMEOUT_MILLIS)
+}
+
+/// Every open game, and how long the server took to answer as a ping.
+pub fn list_games(connection: &Connection) -> Result<(Vec<GameInfo>, Duration), RequestError> {
+    let sent = Instant::now();
+    let games = game_list(connection.ask(Message::ListGames, timeout())?)?;
+    Ok((games, sent.elapsed()))
+}
+
+/// The first request `join` takes. Creating a game is followed by joining
+/// it, everything else is answered with `Joined` straight away.
+pub fn request(player: &str, join: &Join) -> Message {
+    match join {
+        Join::Game(game_id) => Message::JoinGame { game_id: game_id.clone(), player: player.to_string() },
+        Join::Quick => Message::QuickJoin { player: player.to_string() },
+        Join::Create(settings) => new_game(settings),
+        Join::Rejoin(session) => Message::Rejoin { session: session.clone() },
+    }
+}
+
+fn new_game(settings: &GameSettings) -> Message {
+    Message::NewGame {
+        name: settings.name.clone(),
+        max_players: settings.max_players,
+        best_of: settings.best_of,
+        map: settings.map.clone(),
+    }
+}
+
+/// Id of the game created.
+pub fn create_game(connection: &Connection, settings: &GameSettings) -> Result<String, RequestError> {
+    game_created(connection.ask(new_game(settings), timeout())?)
+}
+
+pub fn join_game(connection: &Connection, game_id: &str, player: &str) -> Result<Joined, RequestError> {
+    let msg = Message::JoinGame { game_id: game_id.to_string(), player: player.to_string() };
+    joined(connection.ask(msg, timeout())?)
+}
+
+pub fn quick_join(connection: &Connection, player: &str) -> Result<Joined, RequestError> {
+    joined(connection.ask(Message::QuickJoin { player: player.to_string() }, timeout())?)
+}
+
+/// Takes the player `session` was given for back into their game, from
+/// this connection's address.
+pub fn rejoin(connection: &Connection, session: &str) -> Result<Joined, RequestError> {
+    joined(connection.ask(Message::Rejoin { session: session.to_string() }, timeout())?)
+}
+
+/// Id of the game left.
+pub fn leave_game(connection: &Connection, session: &str) -> Result<String, RequestError> {
+    match connection.ask(Message::LeaveGame { session: session.to_string() }, timeout())? {
+        Message::GameLeft { game_id } => Ok(game_id),
+        _ => Err(RequestError::Unexpected),
+    }
+}
+
+/// Gets `player` into the game `join` asks for. A rejoin ignores `player`,
+/// the server knows who the session was given to.
+pub fn enter(connection: &Connection, player: &str, join: &Join) -> Result<Joined, RequestError> {
+    match join {
+        Join::Game(game_id) => join_game(connection, game_id, player),
+        Join::Quick => quick_join(connection, player),
+        Join::Create(settings) => {
+            let game_id = create_game(connection, settings)?;
+            join_game(connection, &game_id, player)
+        },
+        Join::Rejoin(session) => rejoin(connection, session),
+    }
+}
+
+/// One game per line under a header, for printing.
+pub fn format_game_list(games: &[GameInfo], ping: Duration) -> String {
+    let mut lines = vec![format!("{:<6} {:<16} {:<12} {:>7} {:>7}", "ID", "NAME", "MAP", "PLAYERS", "ROUNDS")];
+    for game in games {
+        lines.push(format!(
+            "{:<6} {:<16} {:<12} {:>7} {:>7}",
+            game.game_id,
+            game.name,
+            game.map,
+            format!("{}/{}", game.players, game.max_players),
+            game.best_of,
+        ));
+    }
+    if games.is_empty() {
+        lines.push("No open games".to_string());
+    }
+    lines.push(format!("Ping {} ms", ping.as_millis()));
+    lines.join("\n")
+}
@@ -0,0 +1,961 @@
+//! Binary wire protocol shared by the client and the game server.
+//!
+//! Every datagram is a single framed message:
+//!
+//! ```text
+//! +------------+---------+----------------+------------------------+
+//! | magic "IW" | version | body length    | body                   |
+//! | 2 bytes    | u8      | u32 big endian | tag u8, then fields    |
+//! +------------+---------+----------------+------------------------+
+//! ```
+//!
+//! Strings are a u16 length followed by UTF-8 bytes, cut short at 65535
+//! bytes, floats are big endian `f32`s and lists are a u16 count followed
+//! by their items. Anything that
+//! doesn't parse comes back as a `DecodeError` rather than a panic, so a bad
+//! packet can only ever cost us that packet.
+
+use std::fmt;
+
+use crate::shared::{Direction, MatchPhase, PlayerInput, Position};
+
+pub const PROTOCOL_MAGIC: [u8; 2] = *b"IW";
+pub const PROTOCOL_VERSION: u8 = 20;
+const HEADER_LEN: usize = 7;
+
+const DIR_UP: u8 = 1;
+const DIR_DOWN: u8 = 1 << 1;
+const DIR_LEFT: u8 = 1 << 2;
+const DIR_RIGHT: u8 = 1 << 3;
+
+const PHASE_LOBBY: u8 = 0;
+const PHASE_COUNTDOWN: u8 = 1;
+const PHASE_PLAYING: u8 = 2;
+const PHASE_ROUND_OVER: u8 = 3;
+const PHASE_FINISHED: u8 = 4;
+
+// Fewest bytes each kind of list item can take up on the wire, so a count
+// claiming more than the rest of the packet could hold isn't believed
+const MIN_GAME_INFO_LEN: usize = 9;
+const MIN_PLAYER_LEN: usize = 78;
+const MIN_PICKUP_LEN: usize = 18;
+const MIN_PROJECTILE_LEN: usize = 20;
+const MIN_INVENTORY_SLOT_LEN: usize = 1;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GameInfo {
+    pub game_id: String,
+    pub name: String,
+    pub players: u8,
+    pub max_players: u8,
+    pub best_of: u8,
+    pub map: String,
+}
+
+/// Everything about a player the server shares with clients.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlayerState {
+    pub name: String,
+    pub body: Position,
+    pub dir: Direction,
+    pub last_dir: Direction,
+    pub hp: i64,
+    pub mp: i64,
+    pub str: i64,
+    pub momentum: f32,
+    pub jumping: bool,
+    /// Seconds into the current jump
+    pub jump_time: f32,
+    pub ready: bool,
+    /// Cleared while the server hasn't heard from the player, the match waits for them
+    pub connected: bool,
+    /// Newest input from this player the server has simulated
+    pub last_input_seq: u32,
+    pub score: u32,
+    /// Simulation steps until the player can attack again
+    pub attack_cooldown: u16,
+    /// Simulation steps the player has no control after being hit
+    pub hit_stun: u16,
+    /// Simulation steps the player can't be hit again
+    pub invulnerable: u16,
+    /// Simulation steps until the player can cast again
+    pub spell_cooldown: u16,
+    /// Simulation steps towards the next point of mp
+    pub mp_regen: u16,
+    pub round_wins: u8,
+    /// Catalog ids of what's in each inventory slot
+    pub inventory: Vec<Option<String>>,
+    pub weapon: Option<String>,
+    pub armor: Option<String>,
+}
+
+/// An item lying in the arena waiting to be picked up.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PickupState {
+    /// Id of the item in the catalog
+    pub item_id: String,
+    pub pos: Position,
+}
+
+/// A spell or thrown item in flight.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProjectileState {
+    pub owner: String,
+    pub body: Position,
+    pub dir: Direction,
+    /// Catalog id of the thrown item, spells have none
+    pub item_id: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct WorldSnapshot {
+    pub game_id: String,
+    /// Name of the map the game is played on
+    pub map: String,
+    /// Milliseconds of game clock on the server when this was taken
+    pub server_time: u64,
+    pub phase: MatchPhase,
+    /// Milliseconds left before the phase moves on, zero while it waits on players
+    pub phase_millis: u32,
+    /// Starts at 1 with the first countdown
+    pub round: u8,
+    pub best_of: u8,
+    pub players: Vec<PlayerState>,
+    pub pickups: Vec<PickupState>,
+    pub projectiles: Vec<ProjectileState>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Message {
+    // Client -> server
+    /// An empty `name` or `map` leaves it up to the server
+    NewGame { name: String, max_players: u8, best_of: u8, map: String },
+    ListGames,
+    JoinGame { game_id: String, player: String },
+    /// Joins the fullest open game, or starts one if there isn't any
+    QuickJoin { player: String },
+    // Everything a player sends once they're in a game carries the session
+    // token the server gave them on joining, in place of their name
+    LeaveGame { session: String },
+    Ready { session: String },
+    GetWorld { session: String },
+    Input { session: String, input: PlayerInput },
+    UseItem { session: String, slot: u8 },
+    DropItem { session: String, slot: u8 },
+    /// Sent every `NET_HEARTBEAT_MILLIS` so the server knows we're still here
+    Heartbeat { session: String },
+    /// Takes a player's place in their game back, from wherever they are now
+    Rejoin { session: String },
+    // Server -> client
+    GameCreated { game_id: String },
+    /// Games still waiting for players, with room left
+    GameList { games: Vec<GameInfo> },
+    World(WorldSnapshot),
+    /// Answers joining, `session` only works from the address that joined.
+    /// `player` is the name the player is in the game as, which after a
+    /// rejoin is the one they first joined with
+    Joined { session: String, player: String, world: WorldSnapshot },
+    GameLeft { game_id: String },
+    /// Pushed to every player each server tick, never sent as a reply
+    Snapshot(WorldSnapshot),
+    ReadyState { ready: bool },
+    Error { reason: String },
+    /// Pushed reliably to every client just before the server goes away
+    ServerShutdown { reason: String },
+    /// Answers the reliable request that went out as `to`, so a reply
+    /// that turns up late is never taken for the answer to a newer one
+    Reply { to: u32, msg: Box<Message> },
+    // Either direction, see `reliable`
+    Reliable { seq: u32, msg: Box<Message> },
+    Ack { seq: u32 },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DecodeError {
+    BadMagic,
+    UnsupportedVersion(u8),
+    Truncated,
+    LengthMismatch { expected: usize, actual: usize },
+    UnknownTag(u8),
+    InvalidString,
+    TrailingBytes(usize),
+    NestedReliable,
+    UnknownPhase(u8),
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DecodeError::BadMagic => write!(f, "not an item wars packet"),
+            DecodeError::UnsupportedVersion(v) => {
+                write!(f, "unsupported protocol version {} (expected {})", v, PROTOCOL_VERSION)
+            },
+            DecodeError::Truncated => write!(f, "packet ended early"),
+            DecodeError::LengthMismatch { expected, actual } => {
+                write!(f, "body length {} does not match header length {}", actual, expected)
+            },
+            DecodeError::UnknownTag(tag) => write!(f, "unknown message tag {}", tag),
+            DecodeError::InvalidString => write!(f, "string is not valid UTF-8"),
+            DecodeError::TrailingBytes(n) => write!(f, "{} unread bytes after message", n),
+            DecodeError::NestedReliable => write!(f, "reliable message or reply wrapped inside another"),
+            DecodeError::UnknownPhase(phase) => write!(f, "unknown match phase {}", phase),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+impl Message {
+    fn tag(&self) -> u8 {
+        match self {
+            Message::NewGame { .. } => 1,
+            Message::ListGames => 2,
+            Message::JoinGame { .. } => 3,
+            Message::Ready { .. } => 4,
+            Message::GetWorld { .. } => 5,
+            Message::Input { .. } => 7,
+            Message::UseItem { .. } => 8,
+            Message::DropItem { .. } => 9,
+            Message::QuickJoin { .. } => 10,
+            Message::LeaveGame { .. } => 11,
+            Message::Heartbeat { .. } => 12,
+            Message::Rejoin { .. } => 13,
+            Message::GameCreated { .. } => 64,
+            Message::GameList { .. } => 65,
+            Message::World(_) => 66,
+            Message::ReadyState { .. } => 67,
+            Message::Error { .. } => 69,
+            Message::Snapshot(_) => 70,
+            Message::ServerShutdown { .. } => 71,
+            Message::GameLeft { .. } => 72,
+            Message::Joined { .. } => 73,
+            Message::Reliable { .. } => 80,
+            Message::Ack { .. } => 81,
+            Message::Reply { .. } => 82,
+        }
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut body = Writer::default();
+        self.write_body(&mut body);
+
+        let mut packet = Vec::with_capacity(HEADER_LEN + body.0.len());
+        packet.extend_from_slice(&PROTOCOL_MAGIC);
+        packet.push(PROTOCOL_VERSION);
+        packet.extend_from_slice(&(body.0.len() as u32).to_be_bytes());
+        packet.extend_from_slice(&body.0);
+        packet
+    }
+
+    fn write_body(&self, body: &mut Writer) {
+        body.put_u8(self.tag());
+        match self {
+            Message::NewGame { name, max_players, best_of, map } => {
+                body.put_str(name);
+                body.put_u8(*max_players);
+                body.put_u8(*best_of);
+                body.put_str(map);
+            },
+            Message::ListGames => (),
+            Message::JoinGame { game_id, player } => {
+                body.put_str(game_id);
+                body.put_str(player);
+            },
+            Message::LeaveGame { session }
+            | Message::Ready { session }
+            | Message::GetWorld { session }
+            | Message::Heartbeat { session }
+            | Message::Rejoin { session } => body.put_str(session),
+            Message::UseItem { session, slot }
+            | Message::DropItem { session, slot } => {
+                body.put_str(session);
+                body.put_u8(*slot);
+            },
+            Message::Input { session, input } => {
+                body.put_str(session);
+                body.put_u32(input.seq);
+                body.put_dir(&input.dir);
+                body.put_bool(input.jump);
+                body.put_bool(input.attack);
+                body.put_bool(input.cast);
+            },
+            Message::QuickJoin { player } => body.put_str(player),
+            Message::GameCreated { game_id } | Message::GameLeft { game_id } => body.put_str(game_id),
+            Message::GameList { games } => {
+                body.put_u16(games.len() as u16);
+                for game in games {
+                    body.put_str(&game.game_id);
+                    body.put_str(&game.name);
+                    body.put_u8(game.players);
+                    body.put_u8(game.max_players);
+                    body.put_u8(game.best_of);
+                    body.put_str(&game.map);
+                }
+            },
+            Message::World(world) | Message::Snapshot(world) => body.put_world(world),
+            Message::Joined { session, player, world } => {
+                body.put_str(session);
+                body.put_str(player);
+                body.put_world(world);
+            },
+            Message::ReadyState { ready } => body.put_bool(*ready),
+            Message::Error { reason } | Message::ServerShutdown { reason } => body.put_str(reason),
+            Message::Reliable { seq, msg } => {
+                body.put_u32(*seq);
+                msg.write_body(body);
+            },
+            Message::Ack { seq } => body.put_u32(*seq),
+            Message::Reply { to, msg } => {
+                body.put_u32(*to);
+                msg.write_body(body);
+            },
+        }
+    }
+
+    pub fn decode(packet: &[u8]) -> Result<Message, DecodeError> {
+        if packet.len() < HEADER_LEN {
+            return Err(DecodeError::Truncated);
+        }
+        if packet[0..2] != PROTOCOL_MAGIC {
+            return Err(DecodeError::BadMagic);
+        }
+        if packet[2] != PROTOCOL_VERSION {
+            return Err(DecodeError::UnsupportedVersion(packet[2]));
+        }
+        let expected = u32::from_be_bytes([packet[3], packet[4], packet[5], packet[6]]) as usize;
+        let body = &packet[HEADER_LEN..];
+        if body.len() != expected {
+            return Err(DecodeError::LengthMismatch { expected, actual: body.len() });
+        }
+
+        let mut reader = Reader { buf: body, pos: 0 };
+        let message = Message::read_body(&mut reader, false)?;
+        let remaining = reader.buf.len() - reader.pos;
+        if remaining != 0 {
+            return Err(DecodeError::TrailingBytes(remaining));
+        }
+        Ok(message)
+    }
+
+    /// `wrapped` is set while reading the inside of a `Reliable` or `Reply`,
+    /// which may not itself be a `Reliable` or `Ack`. A `Reply` can go inside
+    /// a `Reliable` but not inside another `Reply`.
+    fn read_body(reader: &mut Reader, wrapped: bool) -> Result<Message, DecodeError> {
+        let message = match reader.get_u8()? {
+            1 => Message::NewGame {
+                name: reader.get_str()?,
+                max_players: reader.get_u8()?,
+                best_of: reader.get_u8()?,
+                map: reader.get_str()?,
+            },
+            2 => Message::ListGames,
+            3 => Message::JoinGame { game_id: reader.get_str()?, player: reader.get_str()? },
+            4 => Message::Ready { session: reader.get_str()? },
+            5 => Message::GetWorld { session: reader.get_str()? },
+            7 => Message::Input {
+                session: reader.get_str()?,
+                input: PlayerInput {
+                    seq: reader.get_u32()?,
+                    dir: reader.get_dir()?,
+                    jump: reader.get_bool()?,
+                    attack: reader.get_bool()?,
+                    cast: reader.get_bool()?,
+                },
+            },
+            8 => Message::UseItem { session: reader.get_str()?, slot: reader.get_u8()? },
+            9 => Message::DropItem { session: reader.get_str()?, slot: reader.get_u8()? },
+            10 => Message::QuickJoin { player: reader.get_str()? },
+            11 => Message::LeaveGame { session: reader.get_str()? },
+            12 => Message::Heartbeat { session: reader.get_str()? },
+            13 => Message::Rejoin { session: reader.get_str()? },
+            64 => Message::GameCreated { game_id: reader.get_str()? },
+            65 => {
+                let count = reader.get_u16()?;
+                let mut games = Vec::with_capacity(reader.capacity(count, MIN_GAME_INFO_LEN));
+                for _ in 0..count {
+                    games.push(GameInfo {
+                        game_id: reader.get_str()?,
+                        name: reader.get_str()?,
+                        players: reader.get_u8()?,
+                        max_players: reader.get_u8()?,
+                        best_of: reader.get_u8()?,
+                        map: reader.get_str()?,
+                    });
+                }
+                Message::GameList { games }
+            },
+            66 => Message::World(reader.get_world()?),
+            67 => Message::ReadyState { ready: reader.get_bool()? },
+            69 => Message::Error { reason: reader.get_str()? },
+            70 => Message::Snapshot(reader.get_world()?),
+            71 => Message::ServerShutdown { reason: reader.get_str()? },
+            72 => Message::GameLeft { game_id: reader.get_str()? },
+            73 => Message::Joined { session: reader.get_str()?, player: reader.get_str()?, world: reader.get_world()? },
+            80 | 81 if wrapped => return Err(DecodeError::NestedReliable),
+            80 => Message::Reliable { seq: reader.get_u32()?, msg: Box::new(Message::read_body(reader, true)?) },
+            81 => Message::Ack { seq: reader.get_u32()? },
+            82 => {
+                let to = reader.get_u32()?;
+                match Message::read_body(reader, true)? {
+                    Message::Reply { .. } => return Err(DecodeError::NestedReliable),
+                    msg => Message::Reply { to, msg: Box::new(msg) },
+                }
+            },
+            tag => return Err(DecodeError::UnknownTag(tag)),
+        };
+        Ok(message)
+    }
+}
+
+#[derive(Default)]
+struct Writer(Vec<u8>);
+
+impl Writer {
+    fn put_u8(&mut self, value: u8) {
+        self.0.push(value);
+    }
+
+    fn put_u16(&mut self, value: u16) {
+        self.0.extend_from_slice(&value.to_be_bytes());
+    }
+
+    fn put_u32(&mut self, value: u32) {
+        self.0.extend_from_slice(&value.to_be_bytes());
+    }
+
+    fn put_u64(&mut self, value: u64) {
+        self.0.extend_from_slice(&value.to_be_bytes());
+    }
+
+    fn put_i64(&mut self, value: i64) {
+        self.0.extend_from_slice(&value.to_be_bytes());
+    }
+
+    fn put_f32(&mut self, value: f32) {
+        self.0.extend_from_slice(&value.to_be_bytes());
+    }
+
+    fn put_bool(&mut self, value: bool) {
+        self.put_u8(value as u8);
+    }
+
+    /// Anything longer than a u16 length can say is cut at the last whole
+    /// character that fits, rather than sent with a length that's wrapped.
+    fn put_str(&mut self, value: &str) {
+        let mut len = value.len().min(u16::MAX as usize);
+        while !value.is_char_boundary(len) {
+            len -= 1;
+        }
+        self.put_u16(len as u16);
+        self.0.extend_from_slice(&value.as_bytes()[..len]);
+    }
+
+    fn put_opt_str(&mut self, value: &Option<String>) {
+        self.put_bool(value.is_some());
+        if let Some(value) = value {
+            self.put_str(value);
+        }
+    }
+
+    fn put_dir(&mut self, dir: &Direction) {
+        let mut bits = 0;
+        if dir.up { bits |= DIR_UP; }
+        if dir.down { bits |= DIR_DOWN; }
+        if dir.left { bits |= DIR_LEFT; }
+        if dir.right { bits |= DIR_RIGHT; }
+        self.put_u8(bits);
+    }
+
+    fn put_phase(&mut self, phase: MatchPhase) {
+        self.put_u8(match phase {
+            MatchPhase::Lobby => PHASE_LOBBY,
+            MatchPhase::Countdown => PHASE_COUNTDOWN,
+            MatchPhase::Playing => PHASE_PLAYING,
+            MatchPhase::RoundOver => PHASE_ROUND_OVER,
+            MatchPhase::Finished => PHASE_FINISHED,
+        });
+    }
+
+    fn put_position(&mut self, pos: &Position) {
+        self.put_f32(pos.x);
+        self.put_f32(pos.y);
+        self.put_f32(pos.w);
+        self.put_f32(pos.h);
+    }
+
+    fn put_player(&mut self, player: &PlayerState) {
+        self.put_str(&player.name);
+        self.put_position(&player.body);
+        self.put_dir(&player.dir);
+        self.put_dir(&player.last_dir);
+        self.put_i64(player.hp);
+        self.put_i64(player.mp);
+        self.put_i64(player.str);
+        self.put_f32(player.momentum);
+        self.put_bool(player.jumping);
+        self.put_f32(player.jump_time);
+        self.put_bool(player.ready);
+        self.put_bool(player.connected);
+        self.put_u32(player.last_input_seq);
+        self.put_u32(player.score);
+        self.put_u16(player.attack_cooldown);
+        self.put_u16(player.hit_stun);
+        self.put_u16(player.invulnerable);
+        self.put_u16(player.spell_cooldown);
+        self.put_u16(player.mp_regen);
+        self.put_u8(player.round_wins);
+        self.put_u16(player.inventory.len() as u16);
+        for slot in player.inventory.iter() {
+            self.put_opt_str(slot);
+        }
+        self.put_opt_str(&player.weapon);
+        self.put_opt_str(&player.armor);
+    }
+
+    fn put_world(&mut self, world: &WorldSnapshot) {
+        self.put_str(&world.game_id);
+        self.put_str(&world.map);
+        self.put_u64(world.server_time);
+        self.put_phase(world.phase);
+        self.put_u32(world.phase_millis);
+        self.put_u8(world.round);
+        self.put_u8(world.best_of);
+        self.put_u16(world.players.len() as u16);
+        for player in world.players.iter() {
+            self.put_player(player);
+        }
+        self.put_u16(world.pickups.len() as u16);
+        for pickup in world.pickups.iter() {
+            self.put_str(&pickup.item_id);
+            self.put_position(&pickup.pos);
+        }
+        self.put_u16(world.projectiles.len() as u16);
+        for projectile in world.projectiles.iter() {
+            self.put_str(&projectile.owner);
+            self.put_position(&projectile.body);
+            self.put_dir(&projectile.dir);
+            self.put_opt_str(&projectile.item_id);
+        }
+    }
+}
+
+struct Reader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], DecodeError> {
+        if self.buf.len() - self.pos < len {
+            return Err(DecodeError::Truncated);
+        }
+        let bytes = &self.buf[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    /// Room to reserve for a list `count` long whose items take at least
+    /// `min_len` bytes each, never more than the rest of the packet could hold.
+    fn capacity(&self, count: u16, min_len: usize) -> usize {
+        (count as usize).min((self.buf.len() - self.pos) / min_len)
+    }
+
+    fn get_u8(&mut self) -> Result<u8, DecodeError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn get_u16(&mut self) -> Result<u16, DecodeError> {
+        let bytes = self.take(2)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn get_u32(&mut self) -> Result<u32, DecodeError> {
+        let mut bytes = [0; 4];
+        bytes.copy_from_slice(self.take(4)?);
+        Ok(u32::from_be_bytes(bytes))
+    }
+
+    fn get_u64(&mut self) -> Result<u64, DecodeError> {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(self.take(8)?);
+        Ok(u64::from_be_bytes(bytes))
+    }
+
+    fn get_i64(&mut self) -> Result<i64, DecodeError> {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(self.take(8)?);
+        Ok(i64::from_be_bytes(bytes))
+    }
+
+    fn get_f32(&mut self) -> Result<f32, DecodeError> {
+        let mut bytes = [0; 4];
+        bytes.copy_from_slice(self.take(4)?);
+        Ok(f32::from_be_bytes(bytes))
+    }
+
+    fn get_bool(&mut self) -> Result<bool, DecodeError> {
+        Ok(self.get_u8()? != 0)
+    }
+
+    fn get_str(&mut self) -> Result<String, DecodeError> {
+        let len = self.get_u16()? as usize;
+        let bytes = self.take(len)?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| DecodeError::InvalidString)
+    }
+
+    fn get_opt_str(&mut self) -> Result<Option<String>, DecodeError> {
+        if self.get_bool()? {
+            Ok(Some(self.get_str()?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn get_inventory(&mut self) -> Result<Vec<Option<String>>, DecodeError> {
+        let count = self.get_u16()?;
+        let mut inventory = Vec::with_capacity(self.capacity(count, MIN_INVENTORY_SLOT_LEN));
+        for _ in 0..count {
+            inventory.push(self.get_opt_str()?);
+        }
+        Ok(inventory)
+    }
+
+    fn get_dir(&mut self) -> Result<Direction, DecodeError> {
+        let bits = self.get_u8()?;
+        Ok(Direction {
+            up: bits & DIR_UP != 0,
+            down: bits & DIR_DOWN != 0,
+            left: bits & DIR_LEFT != 0,
+            right: bits & DIR_RIGHT != 0,
+        })
+    }
+
+    fn get_phase(&mut self) -> Result<MatchPhase, DecodeError> {
+        match self.get_u8()? {
+            PHASE_LOBBY => Ok(MatchPhase::Lobby),
+            PHASE_COUNTDOWN => Ok(MatchPhase::Countdown),
+            PHASE_PLAYING => Ok(MatchPhase::Playing),
+            PHASE_ROUND_OVER => Ok(MatchPhase::RoundOver),
+            PHASE_FINISHED => Ok(MatchPhase::Finished),
+            phase => Err(DecodeError::UnknownPhase(phase)),
+        }
+    }
+
+    fn get_position(&mut self) -> Result<Position, DecodeError> {
+        Ok(Position { x: self.get_f32()?, y: self.get_f32()?, w: self.get_f32()?, h: self.get_f32()? })
+    }
+
+    fn get_player(&mut self) -> Result<PlayerState, DecodeError> {
+        Ok(PlayerState {
+            name: self.get_str()?,
+            body: self.get_position()?,
+            dir: self.get_dir()?,
+            last_dir: self.get_dir()?,
+            hp: self.get_i64()?,
+            mp: self.get_i64()?,
+            str: self.get_i64()?,
+            momentum: self.get_f32()?,
+            jumping: self.get_bool()?,
+            jump_time: self.get_f32()?,
+            ready: self.get_bool()?,
+            connected: self.get_bool()?,
+            last_input_seq: self.get_u32()?,
+            score: self.get_u32()?,
+            attack_cooldown: self.get_u16()?,
+            hit_stun: self.get_u16()?,
+            invulnerable: self.get_u16()?,
+            spell_cooldown: self.get_u16()?,
+            mp_regen: self.get_u16()?,
+            round_wins: self.get_u8()?,
+            inventory: self.get_inventory()?,
+            weapon: self.get_opt_str()?,
+            armor: self.get_opt_str()?,
+        })
+    }
+
+    fn get_world(&mut self) -> Result<WorldSnapshot, DecodeError> {
+        let game_id = self.get_str()?;
+        let map = self.get_str()?;
+        let server_time = self.get_u64()?;
+        let phase = self.get_phase()?;
+        let phase_millis = self.get_u32()?;
+        let round = self.get_u8()?;
+        let best_of = self.get_u8()?;
+        let count = self.get_u16()?;
+        let mut players = Vec::with_capacity(self.capacity(count, MIN_PLAYER_LEN));
+        for _ in 0..count {
+            players.push(self.get_player()?);
+        }
+        let count = self.get_u16()?;
+        let mut pickups = Vec::with_capacity(self.capacity(count, MIN_PICKUP_LEN));
+        for _ in 0..count {
+            pickups.push(PickupState { item_id: self.get_str()?, pos: self.get_position()? });
+        }
+        let count = self.get_u16()?;
+        let mut projectiles = Vec::with_capacity(self.capacity(count, MIN_PROJECTILE_LEN));
+        for _ in 0..count {
+            projectiles.push(ProjectileState {
+                owner: self.get_str()?,
+                body: self.get_position()?,
+                dir: self.get_dir()?,
+                item_id: self.get_opt_str()?,
+            });
+        }
+        Ok(WorldSnapshot { game_id, map, server_time, phase, phase_millis, round, best_of, players, pickups, projectiles })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn player(name: &str) -> PlayerState {
+        PlayerState {
+            name: name.to_string(),
+            body: Position { x: 1.5, y: -2.0, w: 34.0, h: 44.0 },
+            dir: Direction { up: true, right: true, ..Direction::default() },
+            last_dir: Direction { left: true, ..Direction::default() },
+            hp: 42,
+            mp: -1,
+            str: 7,
+            momentum: 24.5,
+            jumping: true,
+            jump_time: 0.1,
+            ready: true,
+            connected: false,
+            last_input_seq: 99,
+            score: 3,
+            attack_cooldown: 1,
+            hit_stun: 2,
+            invulnerable: 3,
+            spell_cooldown: 4,
+            mp_regen: 5,
+            round_wins: 2,
+            inventory: vec![Some("potion".to_string()), None],
+            weapon: Some("sword".to_string()),
+            armor: None,
+        }
+    }
+
+    fn world() -> WorldSnapshot {
+        WorldSnapshot {
+            game_id: "3".to_string(),
+            map: "arena".to_string(),
+            server_time: 123_456,
+            phase: MatchPhase::RoundOver,
+            phase_millis: 2500,
+            round: 2,
+            best_of: 3,
+            players: vec![player("a"), player("bé")],
+            pickups: vec![PickupState { item_id: "potion".to_string(), pos: Position { x: 1.0, y: 2.0, w: 3.0, h: 4.0 } }],
+            projectiles: vec![ProjectileState {
+                owner: "a".to_string(),
+                body: Position { x: 5.0, y: 6.0, w: 12.0, h: 12.0 },
+                dir: Direction { down: true, ..Direction::default() },
+                item_id: None,
+            }],
+        }
+    }
+
+    /// One of every message.
+    fn messages() -> Vec<Message> {
+        let session = "0b6f3a52".to_string();
+        vec![
+            Message::NewGame { name: "fun".to_string(), max_players: 4, best_of: 5, map: String::new() },
+            Message::ListGames,
+            Message::JoinGame { game_id: "1".to_string(), player: "a".to_string() },
+            Message::QuickJoin { player: "a".to_string() },
+            Message::LeaveGame { session: session.clone() },
+            Message::Ready { session: session.clone() },
+            Message::GetWorld { session: session.clone() },
+            Message::Input {
+                session: session.clone(),
+                input: PlayerInput { seq: 7, dir: Direction { left: true, ..Direction::default() }, jump: true, attack: false, cast: true },
+            },
+            Message::UseItem { session: session.clone(), slot: 2 },
+            Message::DropItem { session: session.clone(), slot: 255 },
+            Message::Heartbeat { session: session.clone() },
+            Message::Rejoin { session: session.clone() },
+            Message::GameCreated { game_id: "1".to_string() },
+            Message::GameList {
+                games: vec![GameInfo {
+                    game_id: "1".to_string(),
+                    name: "fun".to_string(),
+                    players: 1,
+                    max_players: 2,
+                    best_of: 3,
+                    map: "arena".to_string(),
+                }],
+            },
+            Message::World(world()),
+            Message::Joined { session: session.clone(), player: "a".to_string(), world: world() },
+            Message::GameLeft { game_id: "1".to_string() },
+            Message::Snapshot(world()),
+            Message::ReadyState { ready: true },
+            Message::Error { reason: "nope".to_string() },
+            Message::ServerShutdown { reason: "bye".to_string() },
+            Message::Reliable { seq: 12, msg: Box::new(Message::Ready { session }) },
+            Message::Ack { seq: 12 },
+            Message::Reply { to: 3, msg: Box::new(Message::GameLeft { game_id: "1".to_string() }) },
+            Message::Reliable { seq: 13, msg: Box::new(Message::Reply { to: 4, msg: Box::new(Message::World(world())) }) },
+        ]
+    }
+
+    #[test]
+    fn every_message_round_trips() {
+        for msg in messages() {
+            assert_eq!(Message::decode(&msg.encode()), Ok(msg.clone()));
+        }
+    }
+
+    #[test]
+    fn truncated_packets_are_rejected() {
+        for msg in messages() {
+            let packet = msg.encode();
+            for len in 0..packet.len() {
+                assert!(Message::decode(&packet[..len]).is_err(), "{:?} cut to {} bytes", msg, len);
+
+                // Cut short with a header that agrees, so only the body is missing
+                let mut cut = packet[..len.max(HEADER_LEN)].to_vec();
+                let body_len = (cut.len() - HEADER_LEN) as u32;
+                cut[3..HEADER_LEN].copy_from_slice(&body_len.to_be_bytes());
+                if cut.len() < packet.len() {
+                    assert_eq!(Message::decode(&cut), Err(DecodeError::Truncated), "{:?} cut to {} bytes", msg, len);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn garbage_is_rejected_without_panicking() {
+        assert_eq!(Message::decode(b"XX\x11\0\0\0\0"), Err(DecodeError::BadMagic));
+        let mut packet = Message::ListGames.encode();
+        packet[2] = PROTOCOL_VERSION.wrapping_add(1);
+        assert_eq!(Message::decode(&packet), Err(DecodeError::UnsupportedVersion(PROTOCOL_VERSION.wrapping_add(1))));
+        let mut packet = Message::ListGames.encode();
+        packet.push(0);
+        assert_eq!(Message::decode(&packet), Err(DecodeError::LengthMismatch { expected: 1, actual: 2 }));
+
+        let mut body = Writer::default();
+        body.put_u8(200);
+        assert_eq!(Message::decode(&frame(body)), Err(DecodeError::UnknownTag(200)));
+        let mut body = Writer::default();
+        Message::ListGames.write_body(&mut body);
+        body.put_u8(0);
+        assert_eq!(Message::decode(&frame(body)), Err(DecodeError::TrailingBytes(1)));
+        let mut body = Writer::default();
+        body.put_u8(Message::Error { reason: String::new() }.tag());
+        body.put_u16(2);
+        body.0.extend_from_slice(&[0xff, 0xfe]);
+        assert_eq!(Message::decode(&frame(body)), Err(DecodeError::InvalidString));
+        let nested = Message::Reliable { seq: 1, msg: Box::new(Message::Ack { seq: 1 }) };
+        assert_eq!(Message::decode(&nested.encode()), Err(DecodeError::NestedReliable));
+        let nested = Message::Reply { to: 1, msg: Box::new(Message::Reply { to: 2, msg: Box::new(Message::ListGames) }) };
+        assert_eq!(Message::decode(&nested.encode()), Err(DecodeError::NestedReliable));
+        let nested = Message::Reply { to: 1, msg: Box::new(Message::Reliable { seq: 2, msg: Box::new(Message::ListGames) }) };
+        assert_eq!(Message::decode(&nested.encode()), Err(DecodeError::NestedReliable));
+
+        // Every body a small generator comes up with, in a valid frame
+        let mut state: u32 = 0x2545_f491;
+        for _ in 0..20_000 {
+            let mut body = Writer::default();
+            let len = (state >> 24) as usize % 64;
+            for _ in 0..len {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                body.put_u8(state as u8);
+            }
+            let _ = Message::decode(&frame(body));
+        }
+    }
+
+    #[test]
+    fn huge_list_counts_are_not_believed() {
+        let mut body = Writer::default();
+        body.put_u8(Message::World(world()).tag());
+        body.put_str("1");
+        body.put_str("arena");
+        body.put_u64(0);
+        body.put_phase(MatchPhase::Playing);
+        body.put_u32(0);
+        body.put_u8(1);
+        body.put_u8(1);
+        body.put_u16(u16::MAX);
+        let reader = Reader { buf: &body.0, pos: body.0.len() - 2 };
+        assert_eq!(reader.capacity(u16::MAX, MIN_PLAYER_LEN), 0);
+        assert_eq!(Message::decode(&frame(body)), Err(DecodeError::Truncated));
+    }
+
+    #[test]
+    fn smallest_list_items_match_their_minimum_lengths() {
+        let mut smallest = player("");
+        smallest.inventory.clear();
+        smallest.weapon = None;
+        let mut body = Writer::default();
+        body.put_player(&smallest);
+        assert_eq!(body.0.len(), MIN_PLAYER_LEN);
+
+        let mut body = Writer::default();
+        Message::GameList { games: vec![GameInfo {
+            game_id: String::new(),
+            name: String::new(),
+            players: 0,
+            max_players: 0,
+            best_of: 0,
+            map: String::new(),
+        }] }.write_body(&mut body);
+        assert_eq!(body.0.len(), 1 + 2 + MIN_GAME_INFO_LEN);
+
+        let mut empty = world();
+        empty.game_id.clear();
+        empty.map.clear();
+        empty.players.clear();
+        empty.pickups.clear();
+        empty.projectiles.clear();
+        let mut body = Writer::default();
+        body.put_world(&empty);
+        let empty_len = body.0.len();
+        empty.pickups.push(PickupState { item_id: String::new(), pos: Position { x: 0.0, y: 0.0, w: 0.0, h: 0.0 } });
+        let mut body = Writer::default();
+        body.put_world(&empty);
+        assert_eq!(body.0.len() - empty_len, MIN_PICKUP_LEN);
+        empty.pickups.clear();
+        empty.projectiles.push(ProjectileState {
+            owner: String::new(),
+            body: Position { x: 0.0, y: 0.0, w: 0.0, h: 0.0 },
+            dir: Direction::default(),
+            item_id: None,
+        });
+        let mut body = Writer::default();
+        body.put_world(&empty);
+        assert_eq!(body.0.len() - empty_len, MIN_PROJECTILE_LEN);
+
+        let mut body = Writer::default();
+        body.put_opt_str(&None);
+        assert_eq!(body.0.len(), MIN_INVENTORY_SLOT_LEN);
+    }
+
+    #[test]
+    fn long_strings_are_cut_short_not_wrapped() {
+        let long = "a".repeat(70_000);
+        match Message::decode(&Message::Error { reason: long }.encode()) {
+            Ok(Message::Error { reason }) => assert_eq!(reason.len(), u16::MAX as usize),
+            other => panic!("expected Error, got {:?}", other),
+        }
+        // Two byte characters, the last whole one that fits is kept
+        let long = "é".repeat(40_000);
+        match Message::decode(&Message::Error { reason: long }.encode()) {
+            Ok(Message::Error { reason }) => assert_eq!(reason, "é".repeat(u16::MAX as usize / 2)),
+            other => panic!("expected Error, got {:?}", other),
+        }
+    }
+
+    fn frame(body: Writer) -> Vec<u8> {
+        let mut packet = PROTOCOL_MAGIC.to_vec();
+        packet.push(PROTOCOL_VERSION);
+        packet.extend_from_slice(&(body.0.len() as u32).to_be_bytes());
+        packet.extend_from_slice(&body.0);
+        packet
+    }
+}
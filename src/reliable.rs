@@ -0,0 +1,117 @@
+//! Reliable delivery for control messages over UDP.
+//!
+//! Each side keeps a `ReliableChannel` per peer. Reliable messages are
+//! wrapped in `Message::Reliable` with a sequence number and kept until the
+//! peer answers with a matching `Message::Ack`. Unacked messages are resent
+//! with exponential backoff, and a receiver acks every copy it sees but only
+//! hands the first one on, so a retransmit never runs a command twice.
+//! Answers to reliable requests go back reliably too, as a `Message::Reply`
+//! carrying the sequence number of the request they answer.
+//!
+//! Inputs and snapshots don't go through here, for them the latest packet
+//! is the only one worth having.
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use crate::protocol::Message;
+
+const RESEND_INITIAL_MILLIS: u64 = 200;
+const RESEND_MAX_MILLIS: u64 = 2000;
+const RESEND_MAX_ATTEMPTS: u32 = 8;
+/// How many recently received sequence numbers are remembered for duplicate suppression
+const RECEIVED_HISTORY: usize = 256;
+
+struct Pending {
+    seq: u32,
+    packet: Vec<u8>,
+    resend_at: Instant,
+    backoff: Duration,
+    attempts: u32,
+}
+
+pub struct ReliableChannel {
+    next_seq: u32,
+    unacked: Vec<Pending>,
+    received: VecDeque<u32>,
+    /// Last time the peer sent anything reliable or acked anything
+    last_heard: Instant,
+}
+
+impl ReliableChannel {
+    pub fn new() -> ReliableChannel {
+        ReliableChannel {
+            next_seq: 1,
+            unacked: vec![],
+            received: VecDeque::new(),
+            last_heard: Instant::now(),
+        }
+    }
+
+    /// Wraps `msg` for reliable delivery and returns its sequence number
+    /// along with the packet to send now.
+    pub fn send(&mut self, msg: Message) -> (u32, Vec<u8>) {
+        let seq = self.next_seq;
+        self.next_seq = self.next_seq.wrapping_add(1);
+        let packet = Message::Reliable { seq, msg: Box::new(msg) }.encode();
+        let backoff = Duration::from_millis(RESEND_INITIAL_MILLIS);
+        self.unacked.push(Pending {
+            seq,
+            packet: packet.clone(),
+            resend_at: Instant::now() + backoff,
+            backoff,
+            attempts: 1,
+        });
+        (seq, packet)
+    }
+
+    pub fn ack(&mut self, seq: u32) {
+        self.last_heard = Instant::now();
+        self.unacked.retain(|pending| pending.seq != seq);
+    }
+
+    /// Whether everything sent reliably has been acked.
+    pub fn all_acked(&self) -> bool {
+        self.unacked.is_empty()
+    }
+
+    /// How long since the peer last sent a reliable message or an ack.
+    pub fn quiet_for(&self) -> Duration {
+        self.last_heard.elapsed()
+    }
+
+    /// Handles an incoming `Message::Reliable`. Returns the ack to send back
+    /// and the message itself, unless we've already seen it.
+    pub fn receive(&mut self, seq: u32, msg: Message) -> (Vec<u8>, Option<Message>) {
+        self.last_heard = Instant::now();
+        let ack = Message::Ack { seq }.encode();
+        if self.received.contains(&seq) {
+            return (ack, None);
+        }
+        self.received.push_back(seq);
+        if self.received.len() > RECEIVED_HISTORY {
+            self.received.pop_front();
+        }
+        (ack, Some(msg))
+    }
+
+    /// Packets whose ack is overdue. Each is resent with double the previous
+    /// wait, and given up on after `RESEND_MAX_ATTEMPTS`.
+    pub fn resend_due(&mut self) -> Vec<Vec<u8>> {
+        let now = Instant::now();
+        let before = self.unacked.len();
+        self.unacked.retain(|pending| pending.resend_at > now || pending.attempts < RESEND_MAX_ATTEMPTS);
+        if self.unacked.len() < before {
+            println!("Gave up on {} reliable message(s)", before - self.unacked.len());
+        }
+
+        let mut due = vec![];
+        for pending in self.unacked.iter_mut().filter(|p| p.resend_at <= now) {
+            pending.attempts += 1;
+            pending.backoff = (pending.backoff * 2).min(Duration::from_millis(RESEND_MAX_MILLIS));
+            pending.resend_at = now + pending.backoff;
+            due.push(pending.packet.clone());
+        }
+        due
+    }
+}
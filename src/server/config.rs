@@ -0,0 +1,154 @@
+//! Settings for a dedicated server, read from a TOML file.
+//!
+//! Every key is optional and falls back to its default:
+//!
+//! ```toml
+//! bind = "0.0.0.0:7878"
+//! tick_rate = 60
+//! max_games = 16
+//! max_players = 8
+//! default_map = "arena"
+//! # items = "items.json"
+//! # maps = "maps"
+//! ```
+
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use serde::Deserialize;
+
+use crate::shared::DEFAULT_MAP;
+use super::{DEFAULT_SERVER_TICK_RATE, MAX_PLAYERS, MIN_PLAYERS};
+
+pub const DEFAULT_CONFIG_FILE: &str = "server.toml";
+pub const DEFAULT_BIND: &str = "0.0.0.0:7878";
+pub const DEFAULT_MAX_GAMES: usize = 16;
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(io::Error),
+    Parse(toml::de::Error),
+    Invalid(String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::Io(e) => write!(f, "could not read config: {}", e),
+            ConfigError::Parse(e) => write!(f, "could not parse config: {}", e),
+            ConfigError::Invalid(reason) => write!(f, "invalid config: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    /// Address and port to listen on
+    pub bind: String,
+    pub tick_rate: u32,
+    /// Games running at once, new ones are turned away past this
+    pub max_games: usize,
+    /// Most players a single game can be created for
+    pub max_players: u8,
+    /// Map used when a new game doesn't ask for one
+    pub default_map: String,
+    /// Item catalog, `items.json` next to the manifest if not given
+    pub items: Option<String>,
+    /// Directory maps are loaded from, `maps` next to the manifest if not given
+    pub maps: Option<String>,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            bind: DEFAULT_BIND.to_string(),
+            tick_rate: DEFAULT_SERVER_TICK_RATE,
+            max_games: DEFAULT_MAX_GAMES,
+            max_players: MAX_PLAYERS,
+            default_map: DEFAULT_MAP.to_string(),
+            items: None,
+            maps: None,
+        }
+    }
+}
+
+impl ServerConfig {
+    pub fn load(path: &Path) -> Result<ServerConfig, ConfigError> {
+        let text = fs::read_to_string(path).map_err(ConfigError::Io)?;
+        ServerConfig::parse(&text)
+    }
+
+    pub fn parse(text: &str) -> Result<ServerConfig, ConfigError> {
+        let config: ServerConfig = toml::from_str(text).map_err(ConfigError::Parse)?;
+        if config.tick_rate == 0 {
+            return Err(ConfigError::Invalid("tick_rate must be at least 1".to_string()));
+        }
+        if config.max_games == 0 {
+            return Err(ConfigError::Invalid("max_games must be at least 1".to_string()));
+        }
+        if !(MIN_PLAYERS..=MAX_PLAYERS).contains(&config.max_players) {
+            return Err(ConfigError::Invalid(format!("max_players must be {} to {}", MIN_PLAYERS, MAX_PLAYERS)));
+        }
+        Ok(config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn missing_file_is_an_io_error() {
+        let path = Path::new("no/such/dir/server.toml");
+        match ServerConfig::load(path) {
+            Err(ConfigError::Io(e)) => assert_eq!(e.kind(), io::ErrorKind::NotFound),
+            other => panic!("expected an io error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn empty_file_is_the_defaults() {
+        let config = ServerConfig::parse("").unwrap();
+        assert_eq!(config, ServerConfig::default());
+        assert_eq!(config.bind, DEFAULT_BIND);
+        assert_eq!(config.tick_rate, DEFAULT_SERVER_TICK_RATE);
+        assert_eq!(config.max_games, DEFAULT_MAX_GAMES);
+        assert_eq!(config.max_players, MAX_PLAYERS);
+        assert_eq!(config.default_map, DEFAULT_MAP);
+    }
+
+    #[test]
+    fn missing_keys_fall_back_to_defaults() {
+        let config = ServerConfig::parse("tick_rate = 30\nmaps = \"custom\"\n").unwrap();
+        assert_eq!(config, ServerConfig {
+            tick_rate: 30,
+            maps: Some("custom".to_string()),
+            ..ServerConfig::default()
+        });
+    }
+
+    #[test]
+    fn out_of_range_values_are_invalid() {
+        for text in ["tick_rate = 0", "max_games = 0", "max_players = 1", "max_players = 99"] {
+            match ServerConfig::parse(text) {
+                Err(ConfigError::Invalid(_)) => (),
+                other => panic!("{}: expected invalid, got {:?}", text, other),
+            }
+        }
+    }
+
+    #[test]
+    fn bad_types_and_unknown_keys_dont_parse() {
+        for text in ["tick_rate = \"fast\"", "max_players = -1", "port = 7878", "bind ="] {
+            match ServerConfig::parse(text) {
+                Err(ConfigError::Parse(_)) => (),
+                other => panic!("{}: expected a parse error, got {:?}", text, other),
+            }
+        }
+    }
+}
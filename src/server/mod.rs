@@ -0,0 +1,1229 @@
+//! The authoritative game server. Every game runs on its own thread and
+//! pushes snapshots of its world to the players in it.
+
+pub mod config;
+
+use std::collections::HashMap;
+use std::io;
+use std::net::{SocketAddr, UdpSocket};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::protocol::{GameInfo, Message, WorldSnapshot};
+use crate::reliable::ReliableChannel;
+use crate::shared::collision::Solids;
+use crate::shared::items::ItemCatalog;
+use crate::shared::map::TileMap;
+use crate::shared::{MatchPhase, Pickup, Player, Position, Projectile};
+use config::ServerConfig;
+use crate::shared::{NET_TIMEOUT_MILLIS, PLAYER_CELL_HEIGHT, PLAYER_CELL_WIDTH, SIM_STEP_NANOS, SPELL_DAMAGE};
+
+pub const MIN_PLAYERS: u8 = 2;
+pub const MAX_PLAYERS: u8 = 8;
+pub const DEFAULT_MAX_PLAYERS: u8 = 2;
+pub const DEFAULT_BEST_OF: u8 = 3;
+pub const MAX_BEST_OF: u8 = 9;
+pub const DEFAULT_SERVER_TICK_RATE: u32 = 60;
+
+// How long each timed match phase lasts
+const MATCH_COUNTDOWN_MILLIS: u64 = 3000;
+const MATCH_ROUND_OVER_MILLIS: u64 = 3000;
+const MATCH_RESULTS_MILLIS: u64 = 10000;
+
+const ITEMS_PER_GAME: usize = 4;
+const ITEM_RESPAWN_MILLIS: u64 = 8000;
+/// Random spots tried before putting an item off until later
+const ITEM_SPAWN_ATTEMPTS: usize = 20;
+
+/// Damage taken on stepping into a hazard, and again every `HAZARD_STEPS` spent in it
+const HAZARD_DAMAGE: i64 = 5;
+const HAZARD_STEPS: u16 = 30;
+
+const NET_RESEND_CHECK_MILLIS: u64 = 50;
+/// How long after timing out a player in a match can still rejoin it
+const NET_REJOIN_GRACE_MILLIS: u64 = 30_000;
+/// How long an address nobody is playing from has to go quiet before its
+/// reliable state is forgotten. Longer than a peer keeps resending for, so
+/// a late copy of a request can't come in on a fresh channel and run twice
+const NET_CHANNEL_IDLE_MILLIS: u64 = 15_000;
+/// How long shutting down waits for clients to ack that the server is going
+const SHUTDOWN_NOTIFY_MILLIS: u64 = 1000;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NetworkedGame {
+    players: Vec<Player>,
+    max_players: u8,
+    session_id: String,
+    /// Shown in the lobby
+    name: String,
+    phase: MatchPhase,
+    /// Time left in a timed phase
+    #[serde(skip_serializing, skip_deserializing)]
+    phase_timer: Duration,
+    round: u8,
+    best_of: u8,
+    /// Items lying in the arena
+    pickups: Vec<Pickup>,
+    /// How long until each item that was picked up is replaced
+    #[serde(skip_serializing, skip_deserializing)]
+    pickup_respawns: Vec<Duration>,
+    /// Spells and thrown items in flight
+    projectiles: Vec<Projectile>,
+    #[serde(skip_serializing, skip_deserializing)]
+    catalog: Arc<ItemCatalog>,
+    #[serde(skip_serializing, skip_deserializing)]
+    map: Arc<TileMap>,
+    /// Set once the results have been shown, the game is then dropped by the server
+    completed: bool,
+    /// Simulation time owed to the players, consumed in `SIM_STEP_NANOS` steps
+    #[serde(skip_serializing, skip_deserializing)]
+    sim_accumulator: Duration,
+    /// Game clock, stamped on every snapshot
+    #[serde(skip_serializing, skip_deserializing)]
+    clock: Duration,
+}
+
+impl NetworkedGame {
+
+    /// Games not given a name are named after their id.
+    pub fn new(
+        game_id: String,
+        name: String,
+        max_players: u8,
+        best_of: u8,
+        catalog: Arc<ItemCatalog>,
+        map: Arc<TileMap>,
+    ) -> NetworkedGame {
+        let name = if name.is_empty() { format!("Game {}", game_id) } else { name };
+        NetworkedGame {
+            players: vec![],
+            max_players,
+            session_id: game_id,
+            name,
+            phase: MatchPhase::Lobby,
+            phase_timer: Duration::from_millis(0),
+            round: 0,
+            best_of,
+            pickups: vec![],
+            pickup_respawns: vec![],
+            projectiles: vec![],
+            catalog,
+            map,
+            completed: false,
+            sim_accumulator: Duration::from_millis(0),
+            clock: Duration::from_millis(0),
+        }
+    }
+
+    /// Players start on the map's spawn points in the order they joined,
+    /// standing with their feet at the bottom of the tile.
+    fn spawn_point(&self, index: usize) -> Position {
+        let (x, y) = self.map.spawn_points[index % self.map.spawn_points.len()];
+        let tile = self.map.tile_rect(x, y);
+        Position {
+            x: tile.x + (tile.w - PLAYER_CELL_WIDTH) / 2.0,
+            y: tile.y + tile.h - PLAYER_CELL_HEIGHT,
+            w: PLAYER_CELL_WIDTH,
+            h: PLAYER_CELL_HEIGHT,
+        }
+    }
+
+    /// Still in the lobby with room for another player.
+    fn is_open(&self) -> bool {
+        self.phase == MatchPhase::Lobby && self.players.len() < self.max_players as usize
+    }
+
+    fn info(&self) -> GameInfo {
+        GameInfo {
+            game_id: self.session_id.clone(),
+            name: self.name.clone(),
+            players: self.players.len() as u8,
+            max_players: self.max_players,
+            best_of: self.best_of,
+            map: self.map.name.clone(),
+        }
+    }
+
+    /// Adds `name`, playing from `addr`, on the next free spawn point. Only
+    /// games still in the lobby take new players.
+    fn add_player(&mut self, name: String, addr: SocketAddr) -> Result<WorldSnapshot, String> {
+        if self.players.iter().any(|p| p.name == name) {
+            return Err(format!("{} is already in game {}", name, self.session_id));
+        }
+        if self.phase != MatchPhase::Lobby {
+            return Err(format!("Game {} has already started", self.session_id));
+        }
+        if self.players.len() >= self.max_players as usize {
+            println!("game {:?} is full", self.session_id);
+            return Err(format!("Game {} is full", self.session_id));
+        }
+        let player_pos = self.spawn_point(self.players.len());
+        let mut new_player = Player::new(name, player_pos);
+        new_player.addr = Some(addr);
+        println!("{} joined game {}", new_player.name, self.session_id);
+        self.players.push(new_player);
+        Ok(self.snapshot())
+    }
+
+    /// Takes `name` out of the game. A game everyone has left is over.
+    fn remove_player(&mut self, name: &str) -> Result<(), String> {
+        let index = self.players.iter().position(|p| p.name == name)
+            .ok_or_else(|| format!("{} is not in game {}", name, self.session_id))?;
+        self.players.remove(index);
+        println!("{} left game {}", name, self.session_id);
+        self.after_leaving();
+        Ok(())
+    }
+
+    /// Drops `name` after they timed out and never came back.
+    fn drop_player(&mut self, name: &str) {
+        self.players.retain(|p| p.name != name);
+        println!("{} dropped out of game {}", name, self.session_id);
+        self.after_leaving();
+    }
+
+    /// An empty game is over, and a match without enough players left goes
+    /// to whoever is still in it.
+    fn after_leaving(&mut self) {
+        if self.players.is_empty() {
+            self.completed = true;
+        } else if self.in_match() && self.players.len() < MIN_PLAYERS as usize {
+            if let Some(winner) = self.leader() {
+                println!("Game {} won by {} on forfeit", self.session_id, winner.name);
+            }
+            self.enter_phase(MatchPhase::Finished, MATCH_RESULTS_MILLIS);
+        }
+    }
+
+    /// Between the first countdown and the results.
+    fn in_match(&self) -> bool {
+        matches!(self.phase, MatchPhase::Countdown | MatchPhase::Playing | MatchPhase::RoundOver)
+    }
+
+    fn set_connected(&mut self, name: &str, connected: bool) {
+        if let Some(player) = self.players.iter_mut().find(|p| p.name == name && p.connected != connected) {
+            player.connected = connected;
+            if connected {
+                println!("{} reconnected to game {}", name, self.session_id);
+            } else {
+                println!("{} lost connection to game {}", name, self.session_id);
+            }
+        }
+    }
+
+    /// Puts `name` back in the game, now playing from `addr`. Input
+    /// numbering starts over, the client may be a new one counting from 1.
+    fn rejoin(&mut self, name: &str, addr: SocketAddr) -> Result<WorldSnapshot, String> {
+        let game_id = self.session_id.clone();
+        let player = self.players.iter_mut().find(|p| p.name == name)
+            .ok_or_else(|| format!("{} is no longer in game {}", name, game_id))?;
+        player.addr = Some(addr);
+        player.queued_inputs.clear();
+        player.last_input_seq = 0;
+        self.set_connected(name, true);
+        Ok(self.snapshot())
+    }
+
+    fn snapshot(&self) -> WorldSnapshot {
+        WorldSnapshot {
+            game_id: self.session_id.clone(),
+            map: self.map.name.clone(),
+            server_time: self.clock.as_millis() as u64,
+            phase: self.phase,
+            phase_millis: self.phase_timer.as_millis() as u32,
+            round: self.round,
+            best_of: self.best_of,
+            players: self.players.iter().map(|p| p.state()).collect(),
+            pickups: self.pickups.iter().map(|p| p.state()).collect(),
+            projectiles: self.projectiles.iter().map(|p| p.state()).collect(),
+        }
+    }
+
+    fn enter_phase(&mut self, phase: MatchPhase, millis: u64) {
+        self.phase = phase;
+        self.phase_timer = Duration::from_millis(millis);
+    }
+
+    /// Puts everyone back at their spawn point and counts down to the next round.
+    fn start_round(&mut self) {
+        self.round += 1;
+        for index in 0..self.players.len() {
+            let spawn = self.spawn_point(index);
+            self.players[index].respawn(spawn);
+        }
+        self.reset_pickups();
+        self.projectiles.clear();
+        self.sim_accumulator = Duration::from_millis(0);
+        println!("Game {} round {} of {}", self.session_id, self.round, self.best_of);
+        self.enter_phase(MatchPhase::Countdown, MATCH_COUNTDOWN_MILLIS);
+    }
+
+    /// The round goes to the last player standing, or nobody if the last two
+    /// knocked each other out together. The match is over once someone can't
+    /// be caught or every round has been played.
+    fn end_round(&mut self) {
+        let mut standing = self.players.iter_mut().filter(|p| p.hp > 0);
+        match (standing.next(), standing.next()) {
+            (Some(winner), None) => {
+                winner.round_wins += 1;
+                println!("Game {} round {} won by {}", self.session_id, self.round, winner.name);
+            },
+            _ => println!("Game {} round {} was a draw", self.session_id, self.round),
+        }
+
+        let wins_needed = self.best_of / 2 + 1;
+        if self.round >= self.best_of || self.players.iter().any(|p| p.round_wins >= wins_needed) {
+            if let Some(winner) = self.leader() {
+                println!("Game {} won by {}", self.session_id, winner.name);
+            }
+            self.enter_phase(MatchPhase::Finished, MATCH_RESULTS_MILLIS);
+        } else {
+            self.enter_phase(MatchPhase::RoundOver, MATCH_ROUND_OVER_MILLIS);
+        }
+    }
+
+    /// Most rounds won, ties going to whoever knocked out more players and
+    /// then to whoever joined first, the order the client's results list them in.
+    fn leader(&self) -> Option<&Player> {
+        // max_by_key keeps the last of equals, so look from the back
+        self.players.iter().rev().max_by_key(|p| (p.round_wins, p.score))
+    }
+
+    /// Advances the match by `elapsed` of wall time.
+    /// Players always move in fixed `SIM_DT` steps so the server tick rate
+    /// does not change how fast anyone runs.
+    fn tick(&mut self, elapsed: Duration) {
+        self.clock += elapsed;
+        if self.phase == MatchPhase::Lobby {
+            if self.players.len() == self.max_players as usize && self.players.iter().all(|p| p.ready) {
+                self.start_round();
+            }
+            return;
+        } else if self.in_match() && self.players.iter().any(|p| !p.connected) {
+            // The match holds still while someone is missing, in case they make it back
+            return;
+        } else if self.phase != MatchPhase::Playing {
+            self.phase_timer = self.phase_timer.saturating_sub(elapsed);
+            if self.phase_timer > Duration::from_millis(0) {
+                return;
+            }
+            match self.phase {
+                MatchPhase::Countdown => self.enter_phase(MatchPhase::Playing, 0),
+                MatchPhase::RoundOver => self.start_round(),
+                _ => self.completed = true,
+            }
+            return;
+        }
+
+        let step = Duration::from_nanos(SIM_STEP_NANOS);
+        self.sim_accumulator += elapsed;
+        while self.sim_accumulator >= step {
+            let mut attackers = vec![];
+            for index in 0..self.players.len() {
+                // Players move one after another, each blocked by where the others are now
+                let bodies = self.standing_bodies(index);
+                let solids = Solids { map: &self.map, bodies: &bodies };
+                let player = &mut self.players[index];
+                let input = player.queued_inputs.pop_front();
+                // Knocked out players sit the rest of the round out
+                if player.hp <= 0 {
+                    continue;
+                }
+                let actions = player.step(input.as_ref(), &solids);
+                if actions.attacked {
+                    attackers.push(index);
+                }
+                if actions.cast {
+                    self.projectiles.push(player.launch(SPELL_DAMAGE, None));
+                }
+            }
+            self.resolve_attacks(&attackers);
+            self.step_projectiles();
+            self.burn_hazards();
+            self.pick_up_items();
+            self.respawn_pickups(step);
+            self.sim_accumulator -= step;
+            if self.players.iter().filter(|p| p.hp > 0).count() <= 1 {
+                self.end_round();
+                return;
+            }
+        }
+    }
+
+    /// Bodies of everyone still in the round except `index`, knocked out
+    /// players don't block anyone.
+    fn standing_bodies(&self, index: usize) -> Vec<Position> {
+        self.players.iter().enumerate()
+            .filter(|(other, p)| *other != index && p.hp > 0)
+            .map(|(_, p)| p.body)
+            .collect()
+    }
+
+    /// Clears the arena and lays out a fresh set of items.
+    fn reset_pickups(&mut self) {
+        self.pickups.clear();
+        self.pickup_respawns.clear();
+        for _ in 0..ITEMS_PER_GAME {
+            self.spawn_pickup();
+        }
+    }
+
+    /// Drops a random catalog item somewhere in the arena a player can reach
+    /// that isn't under a player or another item. If no such spot turns up
+    /// it is put back on the respawn timer.
+    fn spawn_pickup(&mut self) {
+        let mut rng = rand::thread_rng();
+        if self.map.item_zones.is_empty() {
+            return;
+        }
+        let item = match self.catalog.random_spawn(&mut rng) {
+            Some(item) => item.clone(),
+            None => return,
+        };
+        for _ in 0..ITEM_SPAWN_ATTEMPTS {
+            let (x, y) = self.map.item_zones[rng.gen_range(0, self.map.item_zones.len())];
+            let tile = self.map.tile_rect(x, y);
+            // Centered on the tile, big items spill over onto the tiles around it
+            let pos = Position {
+                x: tile.x + (tile.w - item.size[0]) / 2.0,
+                y: tile.y + (tile.h - item.size[1]) / 2.0,
+                w: item.size[0],
+                h: item.size[1],
+            };
+            if !self.players.iter().any(|p| p.body == pos) && !self.pickups.iter().any(|p| p.pos == pos) {
+                self.pickups.push(Pickup::new(pos, item));
+                return;
+            }
+        }
+        self.pickup_respawns.push(Duration::from_millis(ITEM_RESPAWN_MILLIS));
+    }
+
+    /// The first player standing on an item who has a use for it takes it.
+    fn pick_up_items(&mut self) {
+        let mut index = 0;
+        while index < self.pickups.len() {
+            let pickup = &mut self.pickups[index];
+            if let Some(dropper) = &pickup.dropped_by {
+                if !self.players.iter().any(|p| &p.name == dropper && p.touches(pickup)) {
+                    pickup.dropped_by = None;
+                }
+            }
+
+            let pickup = &self.pickups[index];
+            let mut taken = false;
+            for player in self.players.iter_mut() {
+                if player.touches(pickup) && pickup.dropped_by.as_ref() != Some(&player.name) && player.pick_up(&pickup.item) {
+                    taken = true;
+                    break;
+                }
+            }
+            if taken {
+                if self.pickups.remove(index).respawns {
+                    self.pickup_respawns.push(Duration::from_millis(ITEM_RESPAWN_MILLIS));
+                }
+            } else {
+                index += 1;
+            }
+        }
+    }
+
+    /// The player called `name`, as long as a round is being played.
+    fn playing(&mut self, name: &str) -> Result<&mut Player, String> {
+        if self.phase != MatchPhase::Playing {
+            return Err("items can only be used while playing".to_string());
+        }
+        let game_id = &self.session_id;
+        self.players.iter_mut().find(|p| p.name == name)
+            .ok_or_else(|| format!("{} is not in game {}", name, game_id))
+    }
+
+    fn use_item(&mut self, name: &str, slot: usize) -> Result<(), String> {
+        if let Some(thrown) = self.playing(name)?.use_item(slot)? {
+            self.projectiles.push(thrown);
+        }
+        Ok(())
+    }
+
+    /// Puts what's in `slot` on the ground at the player's feet.
+    fn drop_item(&mut self, name: &str, slot: usize) -> Result<(), String> {
+        let player = self.playing(name)?;
+        if player.hp <= 0 {
+            return Err("knocked out players can't drop items".to_string());
+        }
+        let item = match player.inventory.get_mut(slot) {
+            Some(slot) => slot.take(),
+            None => return Err(format!("there is no slot {}", slot + 1)),
+        };
+        let item = item.ok_or_else(|| format!("slot {} is empty", slot + 1))?;
+        let pos = Position {
+            x: player.body.x + (player.body.w - item.size[0]) / 2.0,
+            y: player.body.y + player.body.h - item.size[1],
+            w: item.size[0],
+            h: item.size[1],
+        };
+        let mut pickup = Pickup::new(pos, item);
+        pickup.respawns = false;
+        pickup.dropped_by = Some(player.name.clone());
+        self.pickups.push(pickup);
+        Ok(())
+    }
+
+    fn respawn_pickups(&mut self, elapsed: Duration) {
+        for timer in self.pickup_respawns.iter_mut() {
+            *timer = timer.saturating_sub(elapsed);
+        }
+        let due = self.pickup_respawns.iter().filter(|timer| **timer == Duration::from_millis(0)).count();
+        self.pickup_respawns.retain(|timer| *timer > Duration::from_millis(0));
+        for _ in 0..due {
+            self.spawn_pickup();
+        }
+    }
+
+    /// Lands every attack started this step. A swing hits anyone overlapping
+    /// the area in front of the attacker unless they're in the air, still
+    /// invulnerable or already knocked out. Each knockout scores a point.
+    fn resolve_attacks(&mut self, attackers: &[usize]) {
+        for &attacker_index in attackers {
+            let hitbox = self.players[attacker_index].attack_hitbox();
+            let facing = self.players[attacker_index].facing();
+            let damage = self.players[attacker_index].attack_damage();
+            let mut kills = 0;
+            for (index, victim) in self.players.iter_mut().enumerate() {
+                if index == attacker_index || victim.jumping || victim.invulnerable > 0 || victim.hp <= 0 || hitbox != victim.body {
+                    continue;
+                }
+                victim.take_hit(damage, &facing);
+                if victim.hp == 0 {
+                    println!("{} was knocked out", victim.name);
+                    kills += 1;
+                }
+            }
+            self.players[attacker_index].score += kills;
+        }
+    }
+
+    /// Hurts everyone standing in a hazard, straight away and then every
+    /// `HAZARD_STEPS` for as long as they stay in it.
+    fn burn_hazards(&mut self) {
+        for player in self.players.iter_mut() {
+            player.hazard_cooldown = player.hazard_cooldown.saturating_sub(1);
+            if player.hp <= 0 || player.hazard_cooldown > 0 || !player.in_hazard(&self.map) {
+                continue;
+            }
+            player.hp = (player.hp - HAZARD_DAMAGE).max(0);
+            player.hazard_cooldown = HAZARD_STEPS;
+            if player.hp == 0 {
+                println!("{} was knocked out", player.name);
+            }
+        }
+    }
+
+    /// Moves every projectile one step. A projectile is spent on the first
+    /// player it hits, other than whoever launched it, under the same rules
+    /// as a swing. A knockout scores a point for its owner.
+    fn step_projectiles(&mut self) {
+        let mut index = 0;
+        while index < self.projectiles.len() {
+            let in_flight = self.projectiles[index].advance(&self.map);
+            let projectile = &self.projectiles[index];
+            let victim = self.players.iter_mut().find(|victim| {
+                victim.name != projectile.owner && !victim.jumping && victim.invulnerable == 0
+                    && victim.hp > 0 && projectile.body == victim.body
+            });
+            if let Some(victim) = victim {
+                victim.take_hit(projectile.damage, &projectile.dir);
+                if victim.hp == 0 {
+                    println!("{} was knocked out", victim.name);
+                    let owner = projectile.owner.clone();
+                    if let Some(owner) = self.players.iter_mut().find(|p| p.name == owner) {
+                        owner.score += 1;
+                    }
+                }
+                self.projectiles.remove(index);
+            } else if !in_flight {
+                self.projectiles.remove(index);
+            } else {
+                index += 1;
+            }
+        }
+    }
+}
+
+/// What a session token stands for: one player in one game, playing from one address.
+struct Session {
+    game_id: String,
+    player: String,
+    addr: SocketAddr,
+    /// Last time anything came in with this session
+    last_seen: Instant,
+    /// Cleared once the player has been silent for `NET_TIMEOUT_MILLIS`
+    connected: bool,
+}
+
+pub struct GameServer {
+    config: ServerConfig,
+    /// Every game is shared with the thread that ticks it
+    games: Vec<Arc<Mutex<NetworkedGame>>>,
+    game_threads: Vec<JoinHandle<()>>,
+    game_count: String,
+    /// Reliable control message state for every client we've heard from
+    channels: HashMap<SocketAddr, ReliableChannel>,
+    /// Every player in a game, by the token they were given on joining
+    sessions: HashMap<String, Session>,
+    catalog: Arc<ItemCatalog>,
+    /// Every map a game can be played on, by name
+    maps: HashMap<String, Arc<TileMap>>,
+    /// Set from another thread to stop `host`
+    shutdown: Arc<AtomicBool>,
+}
+
+impl GameServer {
+
+    pub fn new(config: ServerConfig, catalog: ItemCatalog, maps: HashMap<String, TileMap>) -> GameServer {
+        GameServer {
+            config,
+            games: vec![],
+            game_threads: vec![],
+            game_count: "0".to_string(),
+            channels: HashMap::new(),
+            sessions: HashMap::new(),
+            catalog: Arc::new(catalog),
+            maps: maps.into_iter().map(|(name, map)| (name, Arc::new(map))).collect(),
+            shutdown: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Storing true in this makes `host` tell every client the server is
+    /// going, end every game and return.
+    pub fn shutdown_handle(&self) -> Arc<AtomicBool> {
+        self.shutdown.clone()
+    }
+
+    /// Binds the configured address and serves on it until shut down.
+    pub fn host(&mut self) -> io::Result<()> {
+        let socket = self.bind()?;
+        self.serve(socket);
+        Ok(())
+    }
+
+    /// The socket `serve` wants, bound to the configured address.
+    pub fn bind(&self) -> io::Result<UdpSocket> {
+        let socket = UdpSocket::bind(&self.config.bind)?;
+        // Wake up regularly to retransmit even when nothing arrives
+        socket.set_read_timeout(Some(Duration::from_millis(NET_RESEND_CHECK_MILLIS)))?;
+        Ok(socket)
+    }
+
+    /// Runs every game and answers every client on `socket` until the
+    /// shutdown handle is set.
+    pub fn serve(&mut self, mut socket: UdpSocket) {
+        while !self.shutdown.load(Ordering::SeqCst) {
+            // Finished games stop their own thread, all that's left is to forget them
+            self.games.retain(|game| !game.lock().unwrap().completed);
+            let game_ids: Vec<String> = self.games.iter().map(|game| game.lock().unwrap().session_id.clone()).collect();
+            self.sessions.retain(|_, session| game_ids.contains(&session.game_id));
+            self.check_sessions();
+            self.forget_quiet_channels();
+            self.game_threads.retain(|thread| !thread.is_finished());
+            for (addr, channel) in self.channels.iter_mut() {
+                for packet in channel.resend_due() {
+                    let _ = socket.send_to(&packet, addr);
+                }
+            }
+            let mut buf = [0; 65_000];
+            let (amt, src) = match socket.recv_from(&mut buf) {
+                Ok(received) => received,
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock || e.kind() == io::ErrorKind::TimedOut => continue,
+                // A signal arrived, most likely the one asking us to stop
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => {
+                    println!("Receive failed: {}", e);
+                    continue
+                }
+            };
+            match Message::decode(&buf[0..amt]) {
+                Ok(Message::Ack { seq }) => {
+                    if let Some(channel) = self.channels.get_mut(&src) {
+                        channel.ack(seq);
+                    }
+                },
+                Ok(Message::Reliable { seq, msg }) => {
+                    let channel = self.channels.entry(src).or_insert_with(ReliableChannel::new);
+                    let (ack, msg) = channel.receive(seq, *msg);
+                    let _ = socket.send_to(&ack, src);
+                    if let Some(msg) = msg {
+                        self.handle_connection(msg, &mut socket, src, Some(seq));
+                    }
+                },
+                Ok(msg) => self.handle_connection(msg, &mut socket, src, None),
+                Err(e) => println!("Dropping packet from {}: {}", src, e),
+            }
+        }
+        self.shut_down(&socket);
+    }
+
+    /// Tells every client we've heard from that the server is going and
+    /// waits a moment for their acks, then ends every game.
+    fn shut_down(&mut self, socket: &UdpSocket) {
+        println!("Shutting down, notifying {} clients", self.channels.len());
+        let addrs: Vec<SocketAddr> = self.channels.keys().copied().collect();
+        for addr in addrs {
+            let reason = "The server is shutting down".to_string();
+            self.push(socket, addr, Message::ServerShutdown { reason });
+        }
+        let deadline = Instant::now() + Duration::from_millis(SHUTDOWN_NOTIFY_MILLIS);
+        while Instant::now() < deadline && !self.channels.values().all(|channel| channel.all_acked()) {
+            for (addr, channel) in self.channels.iter_mut() {
+                for packet in channel.resend_due() {
+                    let _ = socket.send_to(&packet, addr);
+                }
+            }
+            let mut buf = [0; 65_000];
+            if let Ok((amt, src)) = socket.recv_from(&mut buf) {
+                if let Ok(Message::Ack { seq }) = Message::decode(&buf[0..amt]) {
+                    if let Some(channel) = self.channels.get_mut(&src) {
+                        channel.ack(seq);
+                    }
+                }
+            }
+        }
+
+        for game in self.games.iter() {
+            game.lock().unwrap().completed = true;
+        }
+        for thread in self.game_threads.drain(..) {
+            let _ = thread.join();
+        }
+        self.games.clear();
+        println!("Server stopped");
+    }
+
+    /// Starts a game once the settings check out. Empty `map` is the
+    /// server's default map.
+    fn create_game(&mut self, socket: &UdpSocket, name: String, max_players: u8, best_of: u8, map: String) -> Result<String, String> {
+        let map = if map.is_empty() { self.config.default_map.clone() } else { map };
+        let spawn_points = self.maps.get(&map).map(|m| m.spawn_points.len());
+        if self.games.len() >= self.config.max_games {
+            Err(format!("The server is already running {} games", self.config.max_games))
+        } else if !(MIN_PLAYERS..=self.config.max_players).contains(&max_players) {
+            Err(format!("Games are for {} to {} players", MIN_PLAYERS, self.config.max_players))
+        } else if !(1..=MAX_BEST_OF).contains(&best_of) {
+            Err(format!("Games are best of 1 to {} rounds", MAX_BEST_OF))
+        } else if spawn_points.is_none() {
+            Err(format!("There is no map {}", map))
+        } else if spawn_points < Some(max_players as usize) {
+            Err(format!("Map {} only has room for {} players", map, spawn_points.unwrap_or(0)))
+        } else {
+            let tile_map = self.maps[&map].clone();
+            Ok(self.new_game(socket, name, max_players, best_of, tile_map))
+        }
+    }
+
+    fn new_game(&mut self, socket: &UdpSocket, name: String, max_players: u8, best_of: u8, map: Arc<TileMap>) -> String {
+        let mut count = self.game_count.parse::<i32>().unwrap();
+        count += 1;
+        self.game_count = count.to_string();
+        let map_name = map.name.clone();
+        let game = NetworkedGame::new(self.game_count.clone(), name, max_players, best_of, self.catalog.clone(), map);
+        let session_id = game.session_id.clone();
+        let game_name = game.name.clone();
+        let shared_game = Arc::new(Mutex::new(game));
+        self.games.push(shared_game.clone());
+        let tick = Duration::from_secs_f32(1.0 / self.config.tick_rate as f32);
+        let socket = socket.try_clone().expect("Could not share server socket with game thread");
+        self.game_threads.push(std::thread::spawn(move || {
+            GameServer::run_game(shared_game, tick, socket);
+        }));
+        println!("Game {} ({}) created on {} for {} players", session_id, game_name, map_name, max_players);
+        session_id
+    }
+
+    /// Ticks one game at a fixed rate until it is marked completed, pushing
+    /// the resulting world to every player after each tick.
+    fn run_game(shared_game: Arc<Mutex<NetworkedGame>>, tick: Duration, socket: UdpSocket) {
+        let mut last_tick = Instant::now();
+        let mut next_tick = last_tick + tick;
+        loop {
+            let (packet, addrs) = {
+                let mut game = shared_game.lock().unwrap();
+                if game.completed {
+                    println!("Game {} completed", game.session_id);
+                    return;
+                }
+                let now = Instant::now();
+                game.tick(now - last_tick);
+                last_tick = now;
+                let addrs: Vec<SocketAddr> = game.players.iter().filter_map(|p| p.addr).collect();
+                (Message::Snapshot(game.snapshot()).encode(), addrs)
+            };
+            for addr in addrs {
+                let _ = socket.send_to(&packet, addr);
+            }
+            let now = Instant::now();
+            if next_tick > now {
+                std::thread::sleep(next_tick - now);
+                next_tick += tick;
+            } else {
+                // Fell behind, don't try to catch up with a burst of ticks
+                next_tick = now + tick;
+            }
+        }
+    }
+
+    /// Puts `player` in the open game closest to filling up, the oldest
+    /// one if there's a tie, or in a new game with the default settings.
+    fn quick_join(&mut self, socket: &UdpSocket, player: String, addr: SocketAddr) -> Result<WorldSnapshot, String> {
+        let fullest = self.games.iter().rev()
+            .filter(|game| {
+                let game = game.lock().unwrap();
+                game.is_open() && !game.players.iter().any(|p| p.name == player)
+            })
+            .max_by_key(|game| game.lock().unwrap().players.len())
+            .cloned();
+        let game = match fullest {
+            Some(game) => game,
+            None => {
+                let game_id = self.create_game(socket, String::new(), DEFAULT_MAX_PLAYERS, DEFAULT_BEST_OF, String::new())?;
+                self.find_game(&game_id).ok_or_else(|| format!("Game {} went away", game_id))?
+            },
+        };
+        let result = game.lock().unwrap().add_player(player, addr);
+        result
+    }
+
+    /// Hands `player` a fresh token for the game they just joined, bound to `addr`.
+    fn open_session(&mut self, player: String, addr: SocketAddr, world: WorldSnapshot) -> Message {
+        let session = Uuid::new_v4().to_string();
+        let game_id = world.game_id.clone();
+        self.sessions.insert(session.clone(), Session {
+            game_id,
+            player: player.clone(),
+            addr,
+            last_seen: Instant::now(),
+            connected: true,
+        });
+        Message::Joined { session, player, world }
+    }
+
+    /// The game and player `session` stands for, as long as it's used from
+    /// the address it was given to. Hearing from a player who had timed out
+    /// brings them back.
+    fn session(&mut self, session: &str, addr: SocketAddr) -> Result<(Arc<Mutex<NetworkedGame>>, String), String> {
+        let (game_id, player, connected) = match self.sessions.get(session) {
+            Some(session) if session.addr != addr => return Err(format!("Session for {} used from {}", session.player, addr)),
+            Some(session) => (session.game_id.clone(), session.player.clone(), session.connected),
+            None => return Err("Unknown session".to_string()),
+        };
+        let game = self.find_game(&game_id).ok_or_else(|| format!("Invalid Game {}", game_id))?;
+        if !connected {
+            game.lock().unwrap().set_connected(&player, true);
+        }
+        if let Some(session) = self.sessions.get_mut(session) {
+            session.last_seen = Instant::now();
+            session.connected = true;
+        }
+        Ok((game, player))
+    }
+
+    /// Moves `session` over to `addr`, wherever the player is playing from
+    /// now. Only a player who has timed out can move, nobody takes over a
+    /// session that's still in use. Hands back the player's name and their game.
+    fn rejoin(&mut self, session: &str, addr: SocketAddr) -> Result<(String, WorldSnapshot), String> {
+        let (game_id, player) = match self.sessions.get(session) {
+            Some(session) if session.connected && session.addr != addr => {
+                return Err(format!("{} is still connected", session.player))
+            },
+            Some(session) => (session.game_id.clone(), session.player.clone()),
+            None => return Err("Unknown session".to_string()),
+        };
+        let game = self.find_game(&game_id).ok_or_else(|| format!("Invalid Game {}", game_id))?;
+        let world = game.lock().unwrap().rejoin(&player, addr)?;
+        if let Some(session) = self.sessions.get_mut(session) {
+            if session.addr != addr {
+                // Nothing more gets through to where they were
+                self.channels.remove(&session.addr);
+            }
+            session.addr = addr;
+            session.last_seen = Instant::now();
+            session.connected = true;
+        }
+        Ok((player, world))
+    }
+
+    /// Marks players we haven't heard from in `NET_TIMEOUT_MILLIS`
+    /// disconnected, and drops them once they've had the grace window to
+    /// come back. Nobody waits for a player who times out of a lobby.
+    fn check_sessions(&mut self) {
+        let timeout = Duration::from_millis(NET_TIMEOUT_MILLIS);
+        let grace = Duration::from_millis(NET_REJOIN_GRACE_MILLIS);
+        let mut expired = vec![];
+        for (token, session) in self.sessions.iter_mut() {
+            let silent = session.last_seen.elapsed();
+            if silent < timeout {
+                continue;
+            }
+            let game = match self.games.iter().find(|g| g.lock().unwrap().session_id == session.game_id) {
+                Some(game) => game,
+                None => continue,
+            };
+            let mut game = game.lock().unwrap();
+            if !game.in_match() || silent >= timeout + grace {
+                game.drop_player(&session.player);
+                self.channels.remove(&session.addr);
+                expired.push(token.clone());
+            } else if session.connected {
+                game.set_connected(&session.player, false);
+                session.connected = false;
+            }
+        }
+        for token in expired {
+            self.sessions.remove(&token);
+        }
+    }
+
+    /// Forgets the reliable state of addresses nobody is playing from, once
+    /// there's nothing left to deliver to them and they've gone quiet. That
+    /// covers players who left, clients that only looked at the lobby and
+    /// anything else that went away without a word.
+    fn forget_quiet_channels(&mut self) {
+        let idle = Duration::from_millis(NET_CHANNEL_IDLE_MILLIS);
+        let playing: Vec<SocketAddr> = self.sessions.values().map(|session| session.addr).collect();
+        self.channels.retain(|addr, channel| playing.contains(addr) || !channel.all_acked() || channel.quiet_for() < idle);
+    }
+
+    fn find_game(&self, game_id: &str) -> Option<Arc<Mutex<NetworkedGame>>> {
+        self.games.iter().find(|g| g.lock().unwrap().session_id == game_id).cloned()
+    }
+
+    /// Answers reliably, tagged with the request's sequence number, when
+    /// the request came in reliably as `request`.
+    fn reply(&mut self, socket: &UdpSocket, addr: SocketAddr, request: Option<u32>, msg: Message) {
+        match request {
+            Some(seq) => self.push(socket, addr, Message::Reply { to: seq, msg: Box::new(msg) }),
+            None => {
+                let _ = socket.send_to(&msg.encode(), addr);
+            },
+        }
+    }
+
+    /// Sends `msg` reliably without it being asked for.
+    fn push(&mut self, socket: &UdpSocket, addr: SocketAddr, msg: Message) {
+        let (_, packet) = self.channels.entry(addr).or_insert_with(ReliableChannel::new).send(msg);
+        let _ = socket.send_to(&packet, addr);
+    }
+
+    fn invalid_game(&mut self, socket: &UdpSocket, addr: SocketAddr, request: Option<u32>, game_id: &str) {
+        println!("Invalid Game {}", game_id);
+        self.reply(socket, addr, request, Message::Error { reason: format!("Invalid Game {}", game_id) });
+    }
+
+    /// `request` is the sequence number `msg` came in with, when it came in reliably.
+    fn handle_connection(&mut self, msg: Message, socket: &mut UdpSocket, addr: SocketAddr, request: Option<u32>) {
+        match msg {
+            Message::NewGame { name, max_players, best_of, map } => {
+                let reply = match self.create_game(socket, name, max_players, best_of, map) {
+                    Ok(game_id) => Message::GameCreated { game_id },
+                    Err(reason) => Message::Error { reason },
+                };
+                self.reply(socket, addr, request, reply);
+            },
+            Message::ListGames => {
+                let games: Vec<GameInfo> = self.games.iter().map(|game| game.lock().unwrap())
+                    .filter(|game| game.is_open()).map(|game| game.info()).collect();
+                self.reply(socket, addr, request, Message::GameList { games });
+            },
+            Message::GetWorld { session } => {
+                let reply = match self.session(&session, addr) {
+                    Ok((game, _)) => Message::World(game.lock().unwrap().snapshot()),
+                    Err(reason) => Message::Error { reason },
+                };
+                self.reply(socket, addr, request, reply);
+            },
+            Message::JoinGame { game_id, player } => {
+                if let Some(game) = self.find_game(&game_id) {
+                    let result = game.lock().unwrap().add_player(player.clone(), addr);
+                    let reply = match result {
+                        Ok(world) => self.open_session(player, addr, world),
+                        Err(reason) => Message::Error { reason },
+                    };
+                    self.reply(socket, addr, request, reply);
+                } else {
+                    self.invalid_game(socket, addr, request, &game_id);
+                }
+            },
+            Message::QuickJoin { player } => {
+                let reply = match self.quick_join(socket, player.clone(), addr) {
+                    Ok(world) => self.open_session(player, addr, world),
+                    Err(reason) => Message::Error { reason },
+                };
+                self.reply(socket, addr, request, reply);
+            },
+            Message::LeaveGame { session } => {
+                let result = self.session(&session, addr).and_then(|(game, player)| {
+                    let mut game = game.lock().unwrap();
+                    game.remove_player(&player)?;
+                    Ok(game.session_id.clone())
+                });
+                let reply = match result {
+                    Ok(game_id) => {
+                        self.sessions.remove(&session);
+                        Message::GameLeft { game_id }
+                    },
+                    Err(reason) => Message::Error { reason },
+                };
+                self.reply(socket, addr, request, reply);
+            },
+            Message::Ready { session } => {
+                let reply = match self.session(&session, addr) {
+                    Ok((game, player)) => {
+                        let mut game = game.lock().unwrap();
+                        for game_player in  game.players.iter_mut() {
+                            if game_player.name == player {
+                                game_player.ready = true;
+                            }
+                        }
+                        Message::ReadyState { ready: game.phase != MatchPhase::Lobby }
+                    },
+                    Err(reason) => Message::Error { reason },
+                };
+                self.reply(socket, addr, request, reply);
+            },
+            Message::Heartbeat { session } => {
+                if let Err(reason) = self.session(&session, addr) {
+                    println!("Dropped heartbeat: {}", reason);
+                }
+            },
+            Message::Rejoin { session } => {
+                let reply = match self.rejoin(&session, addr) {
+                    Ok((player, world)) => Message::Joined { session, player, world },
+                    Err(reason) => Message::Error { reason },
+                };
+                self.reply(socket, addr, request, reply);
+            },
+            Message::Input { session, input } => {
+                // Dropped quietly otherwise, a client that's left or timed out
+                // goes on sending one of these every frame until it notices
+                if let Ok((game, player)) = self.session(&session, addr) {
+                    let mut game = game.lock().unwrap();
+                    if let Some(player) = game.players.iter_mut().find(|p| p.name == player) {
+                        player.queue_input(input);
+                    }
+                }
+            },
+            Message::UseItem { ref session, slot } | Message::DropItem { ref session, slot } => {
+                let result = self.session(session, addr).and_then(|(game, player)| {
+                    let result = if let Message::UseItem { .. } = msg {
+                        game.lock().unwrap().use_item(&player, slot as usize)
+                    } else {
+                        game.lock().unwrap().drop_item(&player, slot as usize)
+                    };
+                    if let Err(reason) = &result {
+                        println!("Rejected item slot {} for {}: {}", slot as usize + 1, player, reason);
+                    }
+                    result
+                });
+                if let Err(reason) = result {
+                    self.reply(socket, addr, request, Message::Error { reason });
+                }
+            },
+            _ => {
+                self.reply(socket, addr, request, Message::Error { reason: "Invalid Command".to_string() });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shared::{load_maps, PlayerInput, DEFAULT_MAP};
+
+    /// A server with one game in it, ticked by nobody so tests decide what happens.
+    fn test_server() -> (GameServer, UdpSocket, String) {
+        let mut server = GameServer::new(ServerConfig::default(), ItemCatalog::default(), load_maps(None, DEFAULT_MAP));
+        let map = server.maps[DEFAULT_MAP].clone();
+        let game = NetworkedGame::new("1".to_string(), String::new(), 2, 1, server.catalog.clone(), map);
+        let game_id = game.session_id.clone();
+        server.games.push(Arc::new(Mutex::new(game)));
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        (server, socket, game_id)
+    }
+
+    fn bind_client() -> (UdpSocket, SocketAddr) {
+        let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+        client.set_read_timeout(Some(Duration::from_secs(1))).unwrap();
+        let addr = client.local_addr().unwrap();
+        (client, addr)
+    }
+
+    /// The next message to arrive, unwrapped when it's a reliable reply.
+    fn receive(client: &UdpSocket) -> Message {
+        let mut buf = [0; 65_000];
+        let (amt, _) = client.recv_from(&mut buf).unwrap();
+        match Message::decode(&buf[0..amt]).unwrap() {
+            Message::Reliable { msg, .. } => match *msg {
+                Message::Reply { msg, .. } => *msg,
+                msg => msg,
+            },
+            msg => msg,
+        }
+    }
+
+    /// Joins reliably, the way a client does, so the server keeps a channel for `addr`.
+    fn join(server: &mut GameServer, socket: &mut UdpSocket, client: &UdpSocket, addr: SocketAddr, game_id: &str, player: &str) -> String {
+        let msg = Message::JoinGame { game_id: game_id.to_string(), player: player.to_string() };
+        server.handle_connection(msg, socket, addr, Some(1));
+        match receive(client) {
+            Message::Joined { session, .. } => session,
+            other => panic!("expected Joined, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn out_of_range_item_slot_is_rejected() {
+        let (mut server, mut socket, game_id) = test_server();
+        let (client, addr) = bind_client();
+        let session = join(&mut server, &mut socket, &client, addr, &game_id, "a");
+        server.find_game(&game_id).unwrap().lock().unwrap().phase = MatchPhase::Playing;
+
+        for msg in [
+            Message::UseItem { session: session.clone(), slot: u8::MAX },
+            Message::DropItem { session, slot: u8::MAX },
+        ] {
+            server.handle_connection(msg, &mut socket, addr, None);
+            match receive(&client) {
+                Message::Error { reason } => assert_eq!(reason, "there is no slot 256"),
+                other => panic!("expected Error, got {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn rejoin_waits_for_the_player_to_time_out() {
+        let (mut server, mut socket, game_id) = test_server();
+        let (client, addr) = bind_client();
+        let (thief, thief_addr) = bind_client();
+        let session = join(&mut server, &mut socket, &client, addr, &game_id, "a");
+        server.find_game(&game_id).unwrap().lock().unwrap().phase = MatchPhase::Playing;
+
+        server.handle_connection(Message::Rejoin { session: session.clone() }, &mut socket, thief_addr, None);
+        match receive(&thief) {
+            Message::Error { reason } => assert_eq!(reason, "a is still connected"),
+            other => panic!("expected Error, got {:?}", other),
+        }
+        assert_eq!(server.sessions[&session].addr, addr);
+
+        server.sessions.get_mut(&session).unwrap().last_seen -= Duration::from_millis(NET_TIMEOUT_MILLIS);
+        server.check_sessions();
+        assert!(!server.sessions[&session].connected);
+        server.handle_connection(Message::Rejoin { session: session.clone() }, &mut socket, thief_addr, None);
+        match receive(&thief) {
+            Message::Joined { player, .. } => assert_eq!(player, "a"),
+            other => panic!("expected Joined, got {:?}", other),
+        }
+        assert_eq!(server.sessions[&session].addr, thief_addr);
+        assert!(!server.channels.contains_key(&addr));
+    }
+
+    #[test]
+    fn timed_out_players_lose_their_channel() {
+        let (mut server, mut socket, game_id) = test_server();
+        let (client, addr) = bind_client();
+        let session = join(&mut server, &mut socket, &client, addr, &game_id, "a");
+        assert!(server.channels.contains_key(&addr));
+        server.check_sessions();
+        server.forget_quiet_channels();
+        assert!(server.channels.contains_key(&addr));
+
+        // Out of a match nobody waits for them
+        server.sessions.get_mut(&session).unwrap().last_seen -= Duration::from_millis(NET_TIMEOUT_MILLIS);
+        server.check_sessions();
+        assert!(server.sessions.is_empty());
+        assert!(!server.channels.contains_key(&addr));
+    }
+
+    #[test]
+    fn world_and_leaving_go_by_session() {
+        let (mut server, mut socket, game_id) = test_server();
+        let (client, addr) = bind_client();
+        let (other, other_addr) = bind_client();
+        let session = join(&mut server, &mut socket, &client, addr, &game_id, "a");
+
+        server.handle_connection(Message::GetWorld { session: "nope".to_string() }, &mut socket, addr, None);
+        assert_eq!(receive(&client), Message::Error { reason: "Unknown session".to_string() });
+        server.handle_connection(Message::GetWorld { session: session.clone() }, &mut socket, other_addr, None);
+        assert!(matches!(receive(&other), Message::Error { .. }));
+        server.handle_connection(Message::GetWorld { session: session.clone() }, &mut socket, addr, None);
+        match receive(&client) {
+            Message::World(world) => assert_eq!(world.players[0].name, "a"),
+            other => panic!("expected World, got {:?}", other),
+        }
+
+        server.handle_connection(Message::LeaveGame { session: session.clone() }, &mut socket, addr, None);
+        assert_eq!(receive(&client), Message::GameLeft { game_id: game_id.clone() });
+        assert!(server.sessions.is_empty());
+        assert!(server.find_game(&game_id).unwrap().lock().unwrap().players.is_empty());
+    }
+
+    #[test]
+    fn ties_go_to_whoever_joined_first() {
+        let (mut server, mut socket, game_id) = test_server();
+        let (client, addr) = bind_client();
+        let (other, other_addr) = bind_client();
+        join(&mut server, &mut socket, &client, addr, &game_id, "a");
+        join(&mut server, &mut socket, &other, other_addr, &game_id, "b");
+        let game = server.find_game(&game_id).unwrap();
+        let mut game = game.lock().unwrap();
+        for player in game.players.iter_mut() {
+            player.round_wins = 1;
+            player.score = 2;
+        }
+        assert_eq!(game.leader().unwrap().name, "a");
+        game.players[1].score = 3;
+        assert_eq!(game.leader().unwrap().name, "b");
+    }
+
+    #[test]
+    fn rejoining_starts_input_numbering_over() {
+        let (mut server, mut socket, game_id) = test_server();
+        let (client, addr) = bind_client();
+        let (restarted, restarted_addr) = bind_client();
+        let session = join(&mut server, &mut socket, &client, addr, &game_id, "a");
+        let game = server.find_game(&game_id).unwrap();
+        game.lock().unwrap().phase = MatchPhase::Playing;
+        game.lock().unwrap().players[0].last_input_seq = 500;
+
+        server.sessions.get_mut(&session).unwrap().last_seen -= Duration::from_millis(NET_TIMEOUT_MILLIS);
+        server.check_sessions();
+        server.handle_connection(Message::Rejoin { session: session.clone() }, &mut socket, restarted_addr, None);
+        assert!(matches!(receive(&restarted), Message::Joined { .. }));
+
+        let input = PlayerInput { seq: 1, ..PlayerInput::default() };
+        server.handle_connection(Message::Input { session, input }, &mut socket, restarted_addr, None);
+        let mut game = game.lock().unwrap();
+        game.tick(Duration::from_nanos(SIM_STEP_NANOS));
+        assert_eq!(game.players[0].last_input_seq, 1);
+    }
+
+    #[test]
+    fn nobody_joins_a_match_in_progress() {
+        let (mut server, mut socket, game_id) = test_server();
+        let (client, addr) = bind_client();
+        let (late, late_addr) = bind_client();
+        join(&mut server, &mut socket, &client, addr, &game_id, "a");
+        // Someone left, there's room but the match is on
+        server.find_game(&game_id).unwrap().lock().unwrap().phase = MatchPhase::Playing;
+
+        let msg = Message::JoinGame { game_id: game_id.clone(), player: "b".to_string() };
+        server.handle_connection(msg, &mut socket, late_addr, None);
+        assert_eq!(receive(&late), Message::Error { reason: format!("Game {} has already started", game_id) });
+        assert_eq!(server.find_game(&game_id).unwrap().lock().unwrap().players.len(), 1);
+        assert_eq!(server.sessions.len(), 1);
+    }
+}
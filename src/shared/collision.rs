@@ -0,0 +1,126 @@
+//! Swept AABB collision for players moving through the arena.
+//!
+//! A move is swept against every solid rectangle in its way instead of only
+//! being checked where it ends up, so nothing tunnels through a wall at
+//! speed. Whatever part of the move runs into something slides along it
+//! rather than being thrown away. The server and the client's prediction
+//! both move players through `move_and_slide` so they always agree.
+
+use crate::shared::map::{Tile, TileMap};
+use crate::shared::{Position, GRID_CELL_SIZE};
+
+/// Overlaps this small are floating point error, not bodies inside each other
+const SKIN: f32 = 0.01;
+
+/// Everything a moving player can bump into.
+pub struct Solids<'a> {
+    pub map: &'a TileMap,
+    /// Other players standing in the way
+    pub bodies: &'a [Position],
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Axis {
+    X,
+    Y,
+}
+
+impl<'a> Solids<'a> {
+    /// Every solid that could be inside `area`. Walls and the map's edges
+    /// always are, low obstacles only for bodies on the ground.
+    fn near(&self, area: &Position, jumping: bool) -> Vec<Position> {
+        let mut solids: Vec<Position> = self.map.tiles_in(area)
+            .filter(|(_, _, tile)| *tile == Tile::Wall || (*tile == Tile::Low && !jumping))
+            .map(|(x, y, _)| self.map.tile_rect(x, y))
+            .collect();
+        let (width, height) = self.map.pixel_size();
+        solids.push(Position { x: -GRID_CELL_SIZE, y: -GRID_CELL_SIZE, w: GRID_CELL_SIZE, h: height + GRID_CELL_SIZE * 2.0 });
+        solids.push(Position { x: -GRID_CELL_SIZE, y: -GRID_CELL_SIZE, w: width + GRID_CELL_SIZE * 2.0, h: GRID_CELL_SIZE });
+        solids.extend(self.bodies.iter().copied());
+        solids
+    }
+}
+
+/// Moves `body` by `(dx, dy)`, stopping against anything solid on the way
+/// and sliding along it with what's left of the move.
+pub fn move_and_slide(body: &Position, dx: f32, dy: f32, solids: &Solids, jumping: bool) -> Position {
+    let mut body = *body;
+    let (mut dx, mut dy) = (dx, dy);
+    // Each hit stops one axis, so there's never more than two
+    for _ in 0..2 {
+        if dx == 0.0 && dy == 0.0 {
+            break;
+        }
+        let area = Position {
+            x: body.x + dx.min(0.0),
+            y: body.y + dy.min(0.0),
+            w: body.w + dx.abs(),
+            h: body.h + dy.abs(),
+        };
+        let hit = solids.near(&area, jumping).iter()
+            .filter_map(|solid| sweep(&body, dx, dy, solid))
+            .min_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal));
+        match hit {
+            Some((time, axis)) => {
+                body.x += dx * time;
+                body.y += dy * time;
+                let remaining = 1.0 - time;
+                if axis == Axis::X {
+                    dx = 0.0;
+                    dy *= remaining;
+                } else {
+                    dy = 0.0;
+                    dx *= remaining;
+                }
+            },
+            None => {
+                body.x += dx;
+                body.y += dy;
+                break;
+            },
+        }
+    }
+    body
+}
+
+/// How far through the move `body` first touches `solid`, from 0 to 1, and
+/// on which axis. None if it never does, or if the two already overlap so
+/// something stuck can always get out.
+fn sweep(body: &Position, dx: f32, dy: f32, solid: &Position) -> Option<(f32, Axis)> {
+    let (x_entry, x_exit) = axis_times(body.x, body.w, dx, solid.x, solid.w)?;
+    let (y_entry, y_exit) = axis_times(body.y, body.h, dy, solid.y, solid.h)?;
+    let entry = x_entry.max(y_entry);
+    if entry == f32::NEG_INFINITY || entry > 1.0 || entry > x_exit.min(y_exit) {
+        return None;
+    }
+    let axis = if x_entry > y_entry { Axis::X } else { Axis::Y };
+    Some((entry, axis))
+}
+
+/// When the span `pos..pos + size` moving by `d` starts and stops
+/// overlapping `solid_pos..solid_pos + solid_size`. Negative infinity if it
+/// already overlaps, None if it never will.
+fn axis_times(pos: f32, size: f32, d: f32, solid_pos: f32, solid_size: f32) -> Option<(f32, f32)> {
+    if d == 0.0 {
+        return if pos + size > solid_pos + SKIN && pos < solid_pos + solid_size - SKIN {
+            Some((f32::NEG_INFINITY, f32::INFINITY))
+        } else {
+            None
+        };
+    }
+    let (gap, far) = if d > 0.0 {
+        (solid_pos - (pos + size), solid_pos + solid_size - pos)
+    } else {
+        (pos - (solid_pos + solid_size), pos + size - solid_pos)
+    };
+    if far <= 0.0 {
+        // Moving away from it
+        return None;
+    }
+    let speed = d.abs();
+    if gap <= -SKIN {
+        Some((f32::NEG_INFINITY, far / speed))
+    } else {
+        Some((gap.max(0.0) / speed, far / speed))
+    }
+}
@@ -0,0 +1,146 @@
+//! Everything that can lie around the arena, loaded from a JSON catalog.
+//!
+//! Each catalog entry describes one kind of item: what it is, its stats,
+//! what it does when used, where its sprite lives and how often it spawns.
+//! Adding an item means adding an entry to the catalog file, the game only
+//! knows about the handful of `ItemKind`s and `Effect`s.
+
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum ItemKind {
+    /// Used up when used, applying its `effects`
+    Consumable,
+    /// Equipped when used, its `str` adds to every attack
+    Weapon,
+    /// Equipped when used, its `defense` comes off every hit taken
+    Armor,
+    /// Thrown at other players for `damage`
+    Throwable,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Effect {
+    RestoreHp(i64),
+    RestoreMp(i64),
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ItemStats {
+    pub str: i64,
+    pub defense: i64,
+    pub damage: i64,
+}
+
+/// Where to find an item's picture.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Sprite {
+    /// Name of a loaded texture, without one the item is drawn as a box of `color`
+    pub texture: Option<String>,
+    /// Part of the texture to draw, as fractions of its width and height
+    pub src: [f32; 4],
+    pub scale: f32,
+    pub color: [f32; 4],
+}
+
+impl Default for Sprite {
+    fn default() -> Self {
+        Sprite {
+            texture: None,
+            src: [0.0, 0.0, 1.0, 1.0],
+            scale: 1.0,
+            color: [1.0, 1.0, 1.0, 1.0],
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Item {
+    /// Unique, and what goes over the wire
+    pub id: String,
+    pub name: String,
+    pub kind: ItemKind,
+    #[serde(default)]
+    pub stats: ItemStats,
+    #[serde(default)]
+    pub effects: Vec<Effect>,
+    #[serde(default)]
+    pub sprite: Sprite,
+    /// Pickup box width and height
+    pub size: [f32; 2],
+    /// Relative chance of being picked when an item spawns, 0 never spawns
+    #[serde(default)]
+    pub spawn_weight: u32,
+}
+
+#[derive(Debug)]
+pub enum CatalogError {
+    Io(io::Error),
+    Parse(serde_json::Error),
+    Invalid(String),
+}
+
+impl fmt::Display for CatalogError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CatalogError::Io(e) => write!(f, "could not read item catalog: {}", e),
+            CatalogError::Parse(e) => write!(f, "could not parse item catalog: {}", e),
+            CatalogError::Invalid(reason) => write!(f, "invalid item catalog: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for CatalogError {}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ItemCatalog {
+    pub items: Vec<Item>,
+}
+
+impl ItemCatalog {
+    pub fn load(path: &Path) -> Result<ItemCatalog, CatalogError> {
+        let json = fs::read_to_string(path).map_err(CatalogError::Io)?;
+        ItemCatalog::parse(&json)
+    }
+
+    pub fn parse(json: &str) -> Result<ItemCatalog, CatalogError> {
+        let catalog: ItemCatalog = serde_json::from_str(json).map_err(CatalogError::Parse)?;
+        for (index, item) in catalog.items.iter().enumerate() {
+            if catalog.items[..index].iter().any(|other| other.id == item.id) {
+                return Err(CatalogError::Invalid(format!("item id {} is used twice", item.id)));
+            }
+            if item.size[0] <= 0.0 || item.size[1] <= 0.0 {
+                return Err(CatalogError::Invalid(format!("item {} needs a positive size", item.id)));
+            }
+        }
+        Ok(catalog)
+    }
+
+    pub fn get(&self, id: &str) -> Option<&Item> {
+        self.items.iter().find(|item| item.id == id)
+    }
+
+    /// A random item, weighted by `spawn_weight`. None if nothing can spawn.
+    pub fn random_spawn<R: Rng>(&self, rng: &mut R) -> Option<&Item> {
+        let total: u32 = self.items.iter().map(|item| item.spawn_weight).sum();
+        if total == 0 {
+            return None;
+        }
+        let mut roll = rng.gen_range(0, total);
+        for item in self.items.iter() {
+            if roll < item.spawn_weight {
+                return Some(item);
+            }
+            roll -= item.spawn_weight;
+        }
+        None
+    }
+}
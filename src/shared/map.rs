@@ -0,0 +1,190 @@
+//! Tile based arenas loaded from plain text files.
+//!
+//! A map is a grid of `GRID_CELL_SIZE` square tiles written one character
+//! per tile, one line per row:
+//!
+//! ```text
+//! #  wall, nothing gets through
+//! o  low obstacle, can be jumped over and shot across
+//! .  floor
+//! ~  hazard, hurts anyone standing in it
+//! S  floor a player can start a round on
+//! I  floor an item can spawn on
+//! ```
+//!
+//! Blank lines and lines starting with `;` are skipped. Every row has to be
+//! the same width, and anything past the edge of the grid counts as wall.
+//! A map is known by its file name without the `.map` extension.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::shared::{Position, GRID_CELL_SIZE};
+
+pub const MAP_EXTENSION: &str = "map";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Tile {
+    Floor,
+    Wall,
+    Low,
+    Hazard,
+}
+
+#[derive(Debug)]
+pub enum MapError {
+    Io(io::Error),
+    Invalid(String),
+}
+
+impl fmt::Display for MapError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MapError::Io(e) => write!(f, "could not read map: {}", e),
+            MapError::Invalid(reason) => write!(f, "invalid map: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for MapError {}
+
+#[derive(Debug, Clone, Default)]
+pub struct TileMap {
+    pub name: String,
+    /// Size in tiles
+    pub width: usize,
+    pub height: usize,
+    /// Row by row, `width` tiles each
+    tiles: Vec<Tile>,
+    /// Tiles players start rounds on, in the order players joined
+    pub spawn_points: Vec<(usize, usize)>,
+    /// Tiles items can spawn on
+    pub item_zones: Vec<(usize, usize)>,
+}
+
+impl TileMap {
+    pub fn load(path: &Path) -> Result<TileMap, MapError> {
+        let text = fs::read_to_string(path).map_err(MapError::Io)?;
+        let name = path.file_stem().and_then(|stem| stem.to_str())
+            .ok_or_else(|| MapError::Invalid(format!("{} has no name", path.display())))?;
+        TileMap::parse(name, &text)
+    }
+
+    /// Every map in `dir`, by name.
+    pub fn load_dir(dir: &Path) -> Result<HashMap<String, TileMap>, MapError> {
+        let mut maps = HashMap::new();
+        for entry in fs::read_dir(dir).map_err(MapError::Io)? {
+            let path = entry.map_err(MapError::Io)?.path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some(MAP_EXTENSION) {
+                continue;
+            }
+            let map = TileMap::load(&path)?;
+            maps.insert(map.name.clone(), map);
+        }
+        Ok(maps)
+    }
+
+    pub fn parse(name: &str, text: &str) -> Result<TileMap, MapError> {
+        let rows: Vec<&str> = text.lines().map(|line| line.trim_end())
+            .filter(|line| !line.is_empty() && !line.starts_with(';')).collect();
+        if rows.is_empty() {
+            return Err(MapError::Invalid(format!("{} has no tiles", name)));
+        }
+        let width = rows[0].chars().count();
+        let mut map = TileMap {
+            name: name.to_string(),
+            width,
+            height: rows.len(),
+            tiles: Vec::with_capacity(width * rows.len()),
+            spawn_points: vec![],
+            item_zones: vec![],
+        };
+        for (y, row) in rows.iter().enumerate() {
+            if row.chars().count() != width {
+                return Err(MapError::Invalid(format!("{} row {} is not {} tiles wide", name, y + 1, width)));
+            }
+            for (x, c) in row.chars().enumerate() {
+                let tile = match c {
+                    '#' => Tile::Wall,
+                    'o' => Tile::Low,
+                    '.' => Tile::Floor,
+                    '~' => Tile::Hazard,
+                    'S' => {
+                        map.spawn_points.push((x, y));
+                        Tile::Floor
+                    },
+                    'I' => {
+                        map.item_zones.push((x, y));
+                        Tile::Floor
+                    },
+                    other => return Err(MapError::Invalid(format!("{} has unknown tile {:?} at {},{}", name, other, x, y))),
+                };
+                map.tiles.push(tile);
+            }
+        }
+        if map.spawn_points.is_empty() {
+            return Err(MapError::Invalid(format!("{} has no spawn points", name)));
+        }
+        Ok(map)
+    }
+
+    /// Width and height in pixels.
+    pub fn pixel_size(&self) -> (f32, f32) {
+        (self.width as f32 * GRID_CELL_SIZE, self.height as f32 * GRID_CELL_SIZE)
+    }
+
+    pub fn tile(&self, x: usize, y: usize) -> Tile {
+        if x < self.width && y < self.height {
+            self.tiles[y * self.width + x]
+        } else {
+            Tile::Wall
+        }
+    }
+
+    /// The tile under a point in pixels.
+    pub fn tile_at(&self, x: f32, y: f32) -> Tile {
+        if x < 0.0 || y < 0.0 {
+            return Tile::Wall;
+        }
+        self.tile((x / GRID_CELL_SIZE) as usize, (y / GRID_CELL_SIZE) as usize)
+    }
+
+    /// Whether any part of `body` is over a wall.
+    pub fn blocked(&self, body: &Position) -> bool {
+        body.x < 0.0 || body.y < 0.0 || self.tiles_in(body).any(|(_, _, tile)| tile == Tile::Wall)
+    }
+
+    /// Every tile `area` covers, including the wall past the right and
+    /// bottom edges. Nothing is returned for the part left of or above the map.
+    pub fn tiles_in(&self, area: &Position) -> impl Iterator<Item = (usize, usize, Tile)> + '_ {
+        let first = (
+            (area.x.max(0.0) / GRID_CELL_SIZE) as usize,
+            (area.y.max(0.0) / GRID_CELL_SIZE) as usize,
+        );
+        // An area ending exactly on a tile edge doesn't reach into the next tile
+        let last = (
+            ((area.x + area.w).max(0.0) / GRID_CELL_SIZE).ceil() as usize,
+            ((area.y + area.h).max(0.0) / GRID_CELL_SIZE).ceil() as usize,
+        );
+        (first.1..last.1).flat_map(move |y| (first.0..last.0).map(move |x| (x, y, self.tile(x, y))))
+    }
+
+    /// Pixel bounds of a tile.
+    pub fn tile_rect(&self, x: usize, y: usize) -> Position {
+        Position {
+            x: x as f32 * GRID_CELL_SIZE,
+            y: y as f32 * GRID_CELL_SIZE,
+            w: GRID_CELL_SIZE,
+            h: GRID_CELL_SIZE,
+        }
+    }
+
+    /// Every tile with its grid coordinates, row by row.
+    pub fn tiles(&self) -> impl Iterator<Item = (usize, usize, Tile)> + '_ {
+        let width = self.width;
+        self.tiles.iter().enumerate().map(move |(index, tile)| (index % width, index / width, *tile))
+    }
+}
@@ -0,0 +1,765 @@
+//! The game simulation, shared by the server and the client's prediction.
+//!
+//! Nothing in here draws or touches a window, so a dedicated server can be
+//! built without any graphics libraries.
+
+pub mod collision;
+pub mod items;
+pub mod map;
+pub mod physics;
+
+use std::collections::{HashMap, VecDeque};
+use std::env;
+use std::net::SocketAddr;
+use std::path;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use crate::protocol::{PickupState, PlayerState, ProjectileState};
+use collision::Solids;
+use items::{Effect, Item, ItemCatalog, ItemKind};
+use map::{Tile, TileMap};
+
+pub const GRID_CELL_SIZE: f32 = 32.0;
+
+/// Longest name the client lets a player pick
+pub const PLAYER_NAME_MAX_CHARS: usize = 8;
+pub const PLAYER_MAX_HP: i64 = 100;
+pub const PLAYER_MAX_MP: i64 = 30;
+pub const PLAYER_MAX_STR: i64 = 10;
+// Speeds are in pixels per second, accelerations in pixels per second per second
+pub const PLAYER_MOVE_SPEED: f32 = 60.0;
+/// Most momentum a player can build up on top of `PLAYER_MOVE_SPEED`
+pub const PLAYER_TOP_MOMENTUM: f32 = 300.0;
+pub const PLAYER_MOMENTUM_GAIN: f32 = 720.0;
+pub const PLAYER_STARTING_MOMENTUM: f32 = 24.0;
+/// Peak of the jump, as a fraction of the sprite's height
+pub const PLAYER_JUMP_HEIGHT: f32 = 0.5;
+pub const PLAYER_JUMP_SECONDS: f32 = 0.2;
+pub const PLAYER_CELL_HEIGHT: f32 = 44.0;
+pub const PLAYER_CELL_WIDTH: f32 = 34.0;
+
+/// How far in front of the player an attack reaches
+pub const ATTACK_RANGE: f32 = 24.0;
+/// Damage dealt for every point of the attacker's `str`
+pub const ATTACK_DAMAGE_PER_STR: i64 = 1;
+pub const ATTACK_KNOCKBACK_SPEED: f32 = 480.0;
+// Combat timers, counted in simulation steps
+pub const ATTACK_COOLDOWN_STEPS: u16 = 30;
+pub const ATTACK_SWING_STEPS: u16 = 8;
+pub const HIT_STUN_STEPS: u16 = 20;
+pub const INVULNERABLE_STEPS: u16 = 45;
+
+pub const SPELL_MP_COST: i64 = 10;
+pub const SPELL_DAMAGE: i64 = 15;
+pub const SPELL_COOLDOWN_STEPS: u16 = 20;
+pub const PROJECTILE_SIZE: f32 = 12.0;
+/// Pixels per second a projectile travels along each axis it's heading in
+pub const PROJECTILE_SPEED: f32 = 480.0;
+pub const PROJECTILE_LIFETIME_STEPS: u16 = 90;
+/// Simulation steps per point of mp regained
+pub const MP_REGEN_STEPS: u16 = 30;
+
+pub const DEFAULT_ITEM_CATALOG: &str = "items.json";
+pub const INVENTORY_SLOTS: usize = 4;
+
+pub const DEFAULT_MAP_DIR: &str = "maps";
+pub const DEFAULT_MAP: &str = "arena";
+
+/// Momentum lost per second once the player stops pushing
+pub const MAP_CURRENT_FRICTION: f32 = 3600.0;
+
+pub const UPDATES_PER_SECOND: f32 = 60.0;
+/// Every simulation step is exactly this long, on the server and the client
+pub const SIM_DT: f32 = 1.0 / UPDATES_PER_SECOND;
+pub const SIM_STEP_NANOS: u64 = (1_000_000_000.0 / UPDATES_PER_SECOND) as u64;
+pub const NET_REQUEST_TIMEOUT_MILLIS: u64 = 2000;
+pub const NET_MAX_QUEUED_INPUTS: usize = 10;
+/// How often a client in a game tells the server it's still there
+pub const NET_HEARTBEAT_MILLIS: u64 = 1000;
+/// Silence from the other side after which a connection counts as lost
+pub const NET_TIMEOUT_MILLIS: u64 = 5000;
+
+
+#[derive(PartialOrd, Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct Position {
+    pub x: f32,
+    pub y: f32,
+    pub w: f32,
+    pub h: f32,
+}
+
+/// Two positions are equal when they overlap, touching edges included.
+impl PartialEq for Position {
+    fn eq(&self, other: &Self) -> bool {
+        self.x <= other.x + other.w && self.x + self.w >= other.x
+            && self.y <= other.y + other.h && self.y + self.h >= other.y
+    }
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Direction {
+    pub up: bool,
+    pub down: bool,
+    pub left: bool,
+    pub right: bool,
+}
+
+/// What a client is allowed to tell the server about its player: which
+/// direction keys are held and whether jump, attack or cast was pressed. The server owns
+/// everything else about the player and simulates it from these inputs.
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PlayerInput {
+    /// Increases by one for every input a client sends
+    pub seq: u32,
+    pub dir: Direction,
+    pub jump: bool,
+    pub attack: bool,
+    pub cast: bool,
+}
+
+/// What a player set off during a step. Only the server acts on these,
+/// turning them into hits and projectiles.
+#[derive(Default, Debug, Clone, Copy, PartialEq)]
+pub struct StepActions {
+    pub attacked: bool,
+    pub cast: bool,
+}
+
+/// Where a match is at. Players only move while `Playing`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum MatchPhase {
+    /// Waiting for the game to fill up and everyone to be ready
+    Lobby,
+    /// Everyone is back at their spawn point, the round starts when it runs out
+    Countdown,
+    Playing,
+    /// One player or none left standing, showing who took the round
+    RoundOver,
+    /// Showing the results before the game is removed
+    Finished,
+}
+
+/// An item from the catalog lying in the arena.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Pickup {
+    pub pos: Position,
+    pub item: Item,
+    /// Spawned items are replaced after being picked up, dropped ones aren't
+    pub respawns: bool,
+    /// Who dropped it here, they can't take it back until they've stepped off
+    pub dropped_by: Option<String>,
+}
+
+impl Pickup {
+
+    pub fn new(pos: Position, item: Item) -> Self {
+        Pickup {
+            pos,
+            item,
+            respawns: true,
+            dropped_by: None,
+        }
+    }
+
+    pub fn state(&self) -> PickupState {
+        PickupState {
+            item_id: self.item.id.clone(),
+            pos: self.pos,
+        }
+    }
+}
+
+/// A spell or a thrown item flying across the arena.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Projectile {
+    /// Who cast or threw it, they can't be hit by it
+    pub owner: String,
+    pub body: Position,
+    pub dir: Direction,
+    pub damage: i64,
+    pub steps_left: u16,
+    /// What was thrown, spells have none
+    pub item: Option<Item>,
+}
+
+impl Projectile {
+
+    pub fn new(
+        owner: String,
+        body: Position,
+        dir: Direction,
+        damage: i64,
+        item: Option<Item>,
+    ) -> Self {
+        Projectile {
+            owner,
+            body,
+            dir,
+            damage,
+            steps_left: PROJECTILE_LIFETIME_STEPS,
+            item,
+        }
+    }
+
+    pub fn state(&self) -> ProjectileState {
+        ProjectileState {
+            owner: self.owner.clone(),
+            body: self.body,
+            dir: self.dir.clone(),
+            item_id: self.item.as_ref().map(|item| item.id.clone()),
+        }
+    }
+
+    /// Moves one step. False once it has hit a wall or run out of steps.
+    pub fn advance(&mut self, map: &TileMap) -> bool {
+        self.body.x += physics::displacement(self.dir.left, self.dir.right, PROJECTILE_SPEED, SIM_DT);
+        self.body.y += physics::displacement(self.dir.up, self.dir.down, PROJECTILE_SPEED, SIM_DT);
+        self.steps_left = self.steps_left.saturating_sub(1);
+        !map.blocked(&self.body) && self.steps_left > 0
+    }
+
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Player {
+    /// First we have the body of the player, which is a single `Segment`.
+    pub body: Position,
+    pub is_hit: bool,
+    /// Then we have the current direction the player is moving. This is
+    /// the direction it will move when `update` is called on it.
+    pub dir: Direction,
+    pub last_dir: Direction,
+    /// Store the direction that will be used in the `update` after the next `update`
+    /// This is needed so a user can press two directions (eg. left then up)
+    /// before one `update` has happened. It sort of queues up key press input
+    pub name: String,
+    pub hp: i64,
+    pub mp: i64,
+    pub str: i64,
+    /// Speed built up on top of `PLAYER_MOVE_SPEED`, carried on after letting go and by knockback
+    pub momentum: f32,
+    pub jumping: bool,
+    /// Seconds into the current jump
+    pub jump_time: f32,
+    pub ready: bool,
+    /// Cleared by the server when it stops hearing from the player
+    pub connected: bool,
+    pub animation_frame: f32,
+    pub animation_total_frames: f32,
+    #[serde(skip_serializing, skip_deserializing)]
+    pub last_animation: Option<std::time::Instant>,
+    pub animation_duration: std::time::Duration,
+    /// Where the server pushes this player's snapshots
+    #[serde(skip_serializing, skip_deserializing)]
+    pub addr: Option<SocketAddr>,
+    /// Inputs received by the server, one is used per simulation step
+    #[serde(skip_serializing, skip_deserializing)]
+    pub queued_inputs: VecDeque<PlayerInput>,
+    /// Sequence number of the last input applied to this player
+    pub last_input_seq: u32,
+    /// Free-for-all, every kill is worth a point
+    pub score: u32,
+    /// Rounds this player was the last one standing
+    pub round_wins: u8,
+    pub inventory: [Option<Item>; INVENTORY_SLOTS],
+    pub weapon: Option<Item>,
+    pub armor: Option<Item>,
+    pub attack_cooldown: u16,
+    /// While above zero the player is being knocked back and ignores input
+    pub hit_stun: u16,
+    pub invulnerable: u16,
+    pub spell_cooldown: u16,
+    /// Steps since the last point of mp came back
+    pub mp_regen: u16,
+    /// Steps until standing in a hazard hurts again, server side only
+    #[serde(skip_serializing, skip_deserializing)]
+    pub hazard_cooldown: u16,
+    /// Drawn this far from `body` while a prediction error is smoothed out
+    #[serde(skip_serializing, skip_deserializing)]
+    pub draw_offset: (f32, f32),
+}
+
+impl Player {
+    pub fn new(name: String, pos: Position) -> Self {
+        // Our player will initially have a body and one body segment,
+        // and will be moving to the right.
+        Player {
+            name,
+            body: pos,
+            dir: Direction::default(),
+            last_dir: Direction::default(),
+            momentum: PLAYER_STARTING_MOMENTUM,
+            hp: PLAYER_MAX_HP,
+            mp: PLAYER_MAX_MP,
+            str: PLAYER_MAX_STR,
+            jumping: false,
+            jump_time: 0.0,
+            ready: false,
+            connected: true,
+            animation_frame: 0.0,
+            animation_total_frames: 4.0,
+            last_animation: Some(std::time::Instant::now()),
+            animation_duration:  Duration::new(0, 150_000_000),
+            is_hit: false,
+            addr: None,
+            queued_inputs: VecDeque::new(),
+            last_input_seq: 0,
+            score: 0,
+            round_wins: 0,
+            inventory: Default::default(),
+            weapon: None,
+            armor: None,
+            attack_cooldown: 0,
+            hit_stun: 0,
+            invulnerable: 0,
+            spell_cooldown: 0,
+            mp_regen: 0,
+            hazard_cooldown: 0,
+            draw_offset: (0.0, 0.0),
+        }
+    }
+
+    /// Items are picked up by walking over them, jumping carries you past.
+    pub fn touches(&self, pickup: &Pickup) -> bool {
+        self.body == pickup.pos && !self.jumping && self.hp > 0
+    }
+
+    /// Puts `item` in the first free inventory slot. False if there isn't one.
+    pub fn pick_up(&mut self, item: &Item) -> bool {
+        match self.inventory.iter_mut().find(|slot| slot.is_none()) {
+            Some(slot) => {
+                *slot = Some(item.clone());
+                true
+            },
+            None => false,
+        }
+    }
+
+    /// Uses whatever is in `slot`. Consumables are used up, weapons and
+    /// armor are swapped with what's equipped and throwables come back as a
+    /// projectile. The server calls this for every use so nothing a client
+    /// claims is taken on trust.
+    pub fn use_item(&mut self, slot: usize) -> Result<Option<Projectile>, String> {
+        if self.hp <= 0 {
+            return Err("knocked out players can't use items".to_string());
+        }
+        let item = match self.inventory.get(slot) {
+            Some(Some(item)) => item.clone(),
+            Some(None) => return Err(format!("slot {} is empty", slot + 1)),
+            None => return Err(format!("there is no slot {}", slot + 1)),
+        };
+        match item.kind {
+            ItemKind::Consumable => {
+                let mut used = false;
+                for effect in item.effects.iter() {
+                    used |= self.apply_effect(effect);
+                }
+                if !used {
+                    return Err(format!("{} would do nothing", item.name));
+                }
+                self.inventory[slot] = None;
+            },
+            ItemKind::Weapon => self.inventory[slot] = self.weapon.replace(item),
+            ItemKind::Armor => self.inventory[slot] = self.armor.replace(item),
+            ItemKind::Throwable => {
+                self.inventory[slot] = None;
+                let damage = item.stats.damage;
+                return Ok(Some(self.launch(damage, Some(item))));
+            },
+        }
+        Ok(None)
+    }
+
+    /// Restores hp or mp, never past the max. False if already full.
+    fn apply_effect(&mut self, effect: &Effect) -> bool {
+        match effect {
+            Effect::RestoreHp(amount) if self.hp < PLAYER_MAX_HP => {
+                self.hp = (self.hp + amount).min(PLAYER_MAX_HP);
+                true
+            },
+            Effect::RestoreMp(amount) if self.mp < PLAYER_MAX_MP => {
+                self.mp = (self.mp + amount).min(PLAYER_MAX_MP);
+                true
+            },
+            _ => false,
+        }
+    }
+
+    /// `str` plus whatever the weapon adds.
+    pub fn attack_damage(&self) -> i64 {
+        let weapon_str = self.weapon.as_ref().map_or(0, |weapon| weapon.stats.str);
+        (self.str + weapon_str) * ATTACK_DAMAGE_PER_STR
+    }
+
+    /// Casting is paid for here so the client's prediction spends mp too.
+    fn apply_input(&mut self, input: &PlayerInput) -> StepActions {
+        let mut actions = StepActions::default();
+        self.last_input_seq = input.seq;
+        if self.hit_stun > 0 {
+            return actions;
+        }
+        self.dir = input.dir.clone();
+        if input.jump && !self.jumping {
+            self.jumping = true;
+        }
+        if input.attack && self.attack_cooldown == 0 {
+            self.attack_cooldown = ATTACK_COOLDOWN_STEPS;
+            actions.attacked = true;
+        }
+        if input.cast && self.spell_cooldown == 0 && self.mp >= SPELL_MP_COST {
+            self.mp -= SPELL_MP_COST;
+            self.spell_cooldown = SPELL_COOLDOWN_STEPS;
+            actions.cast = true;
+        }
+        actions
+    }
+
+    /// One simulation step driven by `input`. The server and the client's
+    /// prediction both go through here so they move the player identically.
+    pub fn step(&mut self, input: Option<&PlayerInput>, solids: &Solids) -> StepActions {
+        self.attack_cooldown = self.attack_cooldown.saturating_sub(1);
+        self.spell_cooldown = self.spell_cooldown.saturating_sub(1);
+        self.hit_stun = self.hit_stun.saturating_sub(1);
+        self.invulnerable = self.invulnerable.saturating_sub(1);
+        self.is_hit = self.hit_stun > 0;
+        self.regen_mp();
+
+        let actions = match input {
+            Some(input) => self.apply_input(input),
+            None => StepActions::default(),
+        };
+        if self.is_hit {
+            // Knocked back, the player's keys do nothing until it wears off
+            self.dir = Direction::default();
+        }
+        self.update(SIM_DT, solids);
+        actions
+    }
+
+    /// A point of mp every `MP_REGEN_STEPS` until it's full again.
+    fn regen_mp(&mut self) {
+        if self.mp >= PLAYER_MAX_MP {
+            self.mp_regen = 0;
+            return;
+        }
+        self.mp_regen += 1;
+        if self.mp_regen >= MP_REGEN_STEPS {
+            self.mp_regen = 0;
+            self.mp += 1;
+        }
+    }
+
+    /// A projectile leaving the middle of the player the way they're facing.
+    pub fn launch(&self, damage: i64, item: Option<Item>) -> Projectile {
+        let (w, h) = match &item {
+            Some(item) => (item.size[0], item.size[1]),
+            None => (PROJECTILE_SIZE, PROJECTILE_SIZE),
+        };
+        let body = Position {
+            x: self.body.x + (self.body.w - w) / 2.0,
+            y: self.body.y + (self.body.h - h) / 2.0,
+            w,
+            h,
+        };
+        Projectile::new(self.name.clone(), body, self.facing(), damage, item)
+    }
+
+    /// The way the player is looking, attacks land on this side.
+    pub fn facing(&self) -> Direction {
+        if self.is_moving() {
+            self.dir.clone()
+        } else if self.last_dir != Direction::default() {
+            self.last_dir.clone()
+        } else {
+            Direction { down: true, ..Direction::default() }
+        }
+    }
+
+    /// The body pushed `ATTACK_RANGE` towards where the player is facing.
+    pub fn attack_hitbox(&self) -> Position {
+        let facing = self.facing();
+        let mut hitbox = self.body;
+        if facing.up {
+            hitbox.y -= ATTACK_RANGE;
+        }
+        if facing.down {
+            hitbox.y += ATTACK_RANGE;
+        }
+        if facing.left {
+            hitbox.x -= ATTACK_RANGE;
+        }
+        if facing.right {
+            hitbox.x += ATTACK_RANGE;
+        }
+        hitbox
+    }
+
+    /// Knocks the player along `from`, the direction the attacker was facing.
+    /// The knockback reuses the momentum `move_direction_cooldown` already slides on.
+    /// Armor soaks up part of `damage` but every hit does at least 1.
+    pub fn take_hit(&mut self, damage: i64, from: &Direction) {
+        let defense = self.armor.as_ref().map_or(0, |armor| armor.stats.defense);
+        self.hp = (self.hp - (damage - defense).max(1)).max(0);
+        self.is_hit = true;
+        self.hit_stun = HIT_STUN_STEPS;
+        self.invulnerable = INVULNERABLE_STEPS;
+        self.dir = Direction::default();
+        self.last_dir = from.clone();
+        self.momentum = ATTACK_KNOCKBACK_SPEED;
+    }
+
+    /// Back at `pos` with full stats and no equipment for a new round.
+    /// Inputs sent for the last round are dropped but still count as seen.
+    pub fn respawn(&mut self, pos: Position) {
+        if let Some(input) = self.queued_inputs.back() {
+            self.last_input_seq = input.seq;
+        }
+        self.queued_inputs.clear();
+        self.body = pos;
+        self.hp = PLAYER_MAX_HP;
+        self.mp = PLAYER_MAX_MP;
+        self.inventory = Default::default();
+        self.weapon = None;
+        self.armor = None;
+        self.dir = Direction::default();
+        self.reset_last_dir();
+        self.momentum = PLAYER_STARTING_MOMENTUM;
+        self.jumping = false;
+        self.jump_time = 0.0;
+        self.is_hit = false;
+        self.attack_cooldown = 0;
+        self.spell_cooldown = 0;
+        self.mp_regen = 0;
+        self.hit_stun = 0;
+        self.invulnerable = 0;
+        self.hazard_cooldown = 0;
+    }
+
+    /// Server side, holds on to an input until the next simulation step.
+    /// Stale or repeated sequence numbers are dropped.
+    pub fn queue_input(&mut self, input: PlayerInput) {
+        let newest_seq = self.queued_inputs.back().map_or(self.last_input_seq, |i| i.seq);
+        if input.seq <= newest_seq {
+            return;
+        }
+        self.queued_inputs.push_back(input);
+        while self.queued_inputs.len() > NET_MAX_QUEUED_INPUTS {
+            self.queued_inputs.pop_front();
+        }
+    }
+
+    pub fn state(&self) -> PlayerState {
+        PlayerState {
+            name: self.name.clone(),
+            body: self.body,
+            dir: self.dir.clone(),
+            last_dir: self.last_dir.clone(),
+            hp: self.hp,
+            mp: self.mp,
+            str: self.str,
+            momentum: self.momentum,
+            jumping: self.jumping,
+            jump_time: self.jump_time,
+            ready: self.ready,
+            connected: self.connected,
+            last_input_seq: self.last_input_seq,
+            score: self.score,
+            attack_cooldown: self.attack_cooldown,
+            hit_stun: self.hit_stun,
+            invulnerable: self.invulnerable,
+            spell_cooldown: self.spell_cooldown,
+            mp_regen: self.mp_regen,
+            round_wins: self.round_wins,
+            inventory: self.inventory.iter().map(|slot| slot.as_ref().map(|item| item.id.clone())).collect(),
+            weapon: self.weapon.as_ref().map(|item| item.id.clone()),
+            armor: self.armor.as_ref().map(|item| item.id.clone()),
+        }
+    }
+
+    /// Takes on the simulated state the server holds for this player.
+    /// Input (`dir`) is left alone, replaying unacked inputs sets it again.
+    /// Items are looked up in `catalog` by id.
+    pub fn sync_from_server(&mut self, server_player: &PlayerState, catalog: &ItemCatalog) {
+        self.body.x = server_player.body.x;
+        self.body.y = server_player.body.y;
+        self.last_dir = server_player.last_dir.clone();
+        self.momentum = server_player.momentum;
+        self.jumping = server_player.jumping;
+        self.jump_time = server_player.jump_time;
+        self.hp = server_player.hp;
+        self.mp = server_player.mp;
+        self.str = server_player.str;
+        self.score = server_player.score;
+        self.attack_cooldown = server_player.attack_cooldown;
+        self.hit_stun = server_player.hit_stun;
+        self.invulnerable = server_player.invulnerable;
+        self.spell_cooldown = server_player.spell_cooldown;
+        self.mp_regen = server_player.mp_regen;
+        self.round_wins = server_player.round_wins;
+        self.is_hit = server_player.hit_stun > 0;
+        let lookup = |id: &Option<String>| id.as_ref().and_then(|id| catalog.get(id)).cloned();
+        for (slot, id) in self.inventory.iter_mut().zip(server_player.inventory.iter()) {
+            *slot = lookup(id);
+        }
+        self.weapon = lookup(&server_player.weapon);
+        self.armor = lookup(&server_player.armor);
+    }
+
+    fn reset_last_dir(&mut self) {
+        self.last_dir.left = false;
+        self.last_dir.right = false;
+        self.last_dir.up = false;
+        self.last_dir.down = false;
+    }
+
+    fn move_direction(&mut self, dt: f32, solids: &Solids) {
+        self.reset_last_dir();
+        self.momentum = physics::approach(self.momentum, PLAYER_TOP_MOMENTUM, PLAYER_MOMENTUM_GAIN, dt);
+        let from = self.body;
+        let dir = self.dir.clone();
+        self.move_towards(&dir, dt, solids);
+        // Only what the player actually managed to move carries on as momentum
+        self.last_dir.up = self.body.y < from.y;
+        self.last_dir.down = self.body.y > from.y;
+        self.last_dir.left = self.body.x < from.x;
+        self.last_dir.right = self.body.x > from.x;
+    }
+
+    fn move_direction_cooldown(&mut self, dt: f32, solids: &Solids) {
+        let dir = self.last_dir.clone();
+        self.move_towards(&dir, dt, solids);
+        self.momentum = physics::approach(self.momentum, 0.0, MAP_CURRENT_FRICTION, dt);
+    }
+
+    /// Moves the body `dt` seconds worth along each axis of `dir`, sliding
+    /// along whatever is in the way.
+    fn move_towards(&mut self, dir: &Direction, dt: f32, solids: &Solids) {
+        let speed = PLAYER_MOVE_SPEED + self.momentum;
+        let dx = physics::displacement(dir.left, dir.right, speed, dt);
+        let dy = physics::displacement(dir.up, dir.down, speed, dt);
+        self.body = collision::move_and_slide(&self.body, dx, dy, solids, self.jumping);
+    }
+
+    /// Whether the player's feet are in a hazard. Jumping clears it.
+    pub fn in_hazard(&self, map: &TileMap) -> bool {
+        !self.jumping && map.tile_at(self.body.x + self.body.w / 2.0, self.body.y + self.body.h - 1.0) == Tile::Hazard
+    }
+
+    /// Where the body is drawn, which trails `body` while a prediction error is smoothed out.
+    pub fn drawn_body(&self) -> Position {
+        Position { x: self.body.x + self.draw_offset.0, y: self.body.y + self.draw_offset.1, ..self.body }
+    }
+
+    fn is_moving(&self) -> bool {
+        self.dir.up || self.dir.down || self.dir.left || self.dir.right
+    }
+
+    /// Advances the jump and movement by `dt` seconds.
+    fn update(&mut self, dt: f32, solids: &Solids) {
+        if self.jumping {
+            self.jump_time += dt;
+            if self.jump_time >= PLAYER_JUMP_SECONDS {
+                self.jumping = false;
+                self.jump_time = 0.0;
+            }
+        } else {
+            self.jump_time = 0.0;
+        }
+        if self.is_moving() {
+            self.move_direction(dt, solids)
+        } else if self.momentum > PLAYER_STARTING_MOMENTUM {
+            self.move_direction_cooldown(dt, solids)
+        }
+    }
+
+    /// How far up the sprite is drawn, as a fraction of its height.
+    pub fn jump_offset(&self) -> f32 {
+        if self.jumping {
+            physics::jump_arc(self.jump_time, PLAYER_JUMP_SECONDS, PLAYER_JUMP_HEIGHT)
+        } else {
+            0.0
+        }
+    }
+
+    pub fn get_animation_direction(&self) -> f32 {
+        if self.dir.up {
+            0.25
+        } else if self.dir.left {
+            0.5
+        } else if self.dir.right {
+            0.75
+        } else if self.dir.down {
+            0.0
+        } else if self.last_dir.left {
+            0.5
+        } else if self.last_dir.right {
+           0.75
+        } else if self.last_dir.up {
+            0.25
+        } else {
+            0.0
+        }
+    }
+
+    pub fn animate_frames(&mut self) {
+        // Animation movement
+        if self.is_moving() && self.last_animation.unwrap().elapsed() > self.animation_duration {
+            self.last_animation = Some(Instant::now());
+            self.animation_frame += 1.0 / self.animation_total_frames;
+            if self.animation_frame >= 1.0 {
+                self.animation_frame = 0.0;
+            }
+        }
+    }
+}
+
+/// Reads the item catalog from `file`, or `items.json` next to the textures by default.
+pub fn load_item_catalog(file: Option<&str>) -> ItemCatalog {
+    let catalog_path = match file {
+        Some(file) => path::PathBuf::from(file),
+        None => if let Ok(manifest_dir) = env::var("CARGO_MANIFEST_DIR") {
+            let mut path = path::PathBuf::from(manifest_dir);
+            path.push(DEFAULT_ITEM_CATALOG);
+            path
+        } else {
+            path::PathBuf::from(DEFAULT_ITEM_CATALOG)
+        },
+    };
+    match ItemCatalog::load(&catalog_path) {
+        Ok(catalog) => catalog,
+        Err(e) => panic!("{}: {}", catalog_path.display(), e),
+    }
+}
+
+/// Where maps are looked for, `DEFAULT_MAP_DIR` next to the manifest unless `dir` is given.
+pub fn map_dir(dir: Option<&str>) -> path::PathBuf {
+    match dir {
+        Some(dir) => path::PathBuf::from(dir),
+        None => if let Ok(manifest_dir) = env::var("CARGO_MANIFEST_DIR") {
+            let mut path = path::PathBuf::from(manifest_dir);
+            path.push(DEFAULT_MAP_DIR);
+            path
+        } else {
+            path::PathBuf::from(DEFAULT_MAP_DIR)
+        },
+    }
+}
+
+/// Every map in the map directory, which has to include `default_map`.
+pub fn load_maps(dir: Option<&str>, default_map: &str) -> HashMap<String, TileMap> {
+    let map_dir = map_dir(dir);
+    match TileMap::load_dir(&map_dir) {
+        Ok(maps) if maps.contains_key(default_map) => maps,
+        Ok(_) => panic!("{}: there is no {} map", map_dir.display(), default_map),
+        Err(e) => panic!("{}: {}", map_dir.display(), e),
+    }
+}
+
+/// The map called `name` from the map directory.
+pub fn load_map(dir: Option<&str>, name: &str) -> Result<TileMap, map::MapError> {
+    let mut map_path = map_dir(dir);
+    map_path.push(format!("{}.{}", name, map::MAP_EXTENSION));
+    TileMap::load(&map_path)
+}
@@ -0,0 +1,139 @@
+//! Movement maths for the simulation.
+//!
+//! Everything here is a plain function of the time step `dt` in seconds
+//! with no window or clock behind it. The simulation always steps by the
+//! same `SIM_DT`, so the server and the client's prediction get identical
+//! results however fast either of them happens to be running.
+
+/// Moves `value` towards `target` at `rate` per second, never past it.
+pub fn approach(value: f32, target: f32, rate: f32, dt: f32) -> f32 {
+    if value < target {
+        (value + rate * dt).min(target)
+    } else {
+        (value - rate * dt).max(target)
+    }
+}
+
+/// Height of a jump `elapsed` seconds into it. The arc is a parabola that
+/// peaks at `peak` halfway through and lands after `duration` seconds.
+pub fn jump_arc(elapsed: f32, duration: f32, peak: f32) -> f32 {
+    let t = (elapsed / duration).clamp(0.0, 1.0);
+    4.0 * peak * t * (1.0 - t)
+}
+
+/// How far something moving at `speed` per second gets along one axis in
+/// `dt`, given whether it's heading towards the negative or positive end.
+/// Heading both ways at once cancels out.
+pub fn displacement(negative: bool, positive: bool, speed: f32, dt: f32) -> f32 {
+    match (negative, positive) {
+        (true, false) => -speed * dt,
+        (false, true) => speed * dt,
+        _ => 0.0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    use crate::shared::collision::Solids;
+    use crate::shared::map::TileMap;
+    use crate::shared::{Direction, Player, PlayerInput, Position, PLAYER_JUMP_SECONDS, SIM_DT, SIM_STEP_NANOS};
+
+    /// Open floor with room to run for a good while in any direction.
+    fn open_map() -> TileMap {
+        let mut rows = vec![".".repeat(60); 20];
+        rows[10].replace_range(30..31, "S");
+        TileMap::parse("open", &rows.join("\n")).unwrap()
+    }
+
+    fn player() -> Player {
+        Player::new("a".to_string(), Position { x: 400.0, y: 300.0, w: 34.0, h: 44.0 })
+    }
+
+    #[test]
+    fn approach_never_overshoots() {
+        for &(value, target) in &[(0.0, 10.0), (10.0, 0.0), (-5.0, 5.0), (3.0, 3.0)] {
+            let mut current = value;
+            for _ in 0..100 {
+                let next = approach(current, target, 7.0, 0.3);
+                assert!((next - target).abs() <= (current - target).abs());
+                assert!((value <= target && next <= target) || (value >= target && next >= target));
+                current = next;
+            }
+            assert_eq!(current, target);
+        }
+        // A step far bigger than the gap lands right on it
+        assert_eq!(approach(0.0, 1.0, 1000.0, 1.0), 1.0);
+        assert_eq!(approach(1.0, 0.0, 1000.0, 1.0), 0.0);
+    }
+
+    #[test]
+    fn jump_arc_peaks_halfway_and_lands() {
+        assert_eq!(jump_arc(0.0, 0.2, 0.5), 0.0);
+        assert!((jump_arc(0.1, 0.2, 0.5) - 0.5).abs() < 1e-6);
+        assert_eq!(jump_arc(0.2, 0.2, 0.5), 0.0);
+        assert_eq!(jump_arc(1.0, 0.2, 0.5), 0.0);
+    }
+
+    #[test]
+    fn displacement_cancels_out() {
+        assert_eq!(displacement(true, false, 60.0, 0.5), -30.0);
+        assert_eq!(displacement(false, true, 60.0, 0.5), 30.0);
+        assert_eq!(displacement(true, true, 60.0, 0.5), 0.0);
+        assert_eq!(displacement(false, false, 60.0, 0.5), 0.0);
+    }
+
+    #[test]
+    fn jump_lands_after_jump_seconds() {
+        let map = open_map();
+        let solids = Solids { map: &map, bodies: &[] };
+        let mut player = player();
+        let jump = PlayerInput { jump: true, ..PlayerInput::default() };
+        player.step(Some(&jump), &solids);
+        let mut steps = 1;
+        while player.jumping {
+            assert!(player.jump_offset() > 0.0);
+            player.step(None, &solids);
+            steps += 1;
+            assert!(steps < 1000, "the jump never landed");
+        }
+        let airborne = steps as f32 * SIM_DT;
+        assert!(airborne >= PLAYER_JUMP_SECONDS - 1e-4, "landed after {}s", airborne);
+        assert!(airborne <= PLAYER_JUMP_SECONDS + SIM_DT, "landed after {}s", airborne);
+        assert_eq!(player.jump_offset(), 0.0);
+    }
+
+    /// Where the player ends up holding `input` for a second, ticked at
+    /// `hz` the way the server's game loop ticks the simulation.
+    fn run_for_a_second(hz: u32, input: &PlayerInput) -> Position {
+        let map = open_map();
+        let solids = Solids { map: &map, bodies: &[] };
+        let mut player = player();
+        let step = Duration::from_nanos(SIM_STEP_NANOS);
+        let mut accumulator = Duration::from_millis(0);
+        for _ in 0..hz {
+            accumulator += Duration::from_secs(1) / hz;
+            while accumulator >= step {
+                accumulator -= step;
+                player.step(Some(input), &solids);
+            }
+        }
+        player.body
+    }
+
+    #[test]
+    fn same_displacement_at_any_tick_rate() {
+        let input = PlayerInput {
+            dir: Direction { right: true, down: true, ..Direction::default() },
+            ..PlayerInput::default()
+        };
+        let at_60 = run_for_a_second(60, &input);
+        assert!(at_60.x > 400.0 && at_60.y > 300.0);
+        for &hz in &[20, 30] {
+            let body = run_for_a_second(hz, &input);
+            assert_eq!((body.x, body.y), (at_60.x, at_60.y), "at {} Hz", hz);
+        }
+    }
+}